@@ -0,0 +1,160 @@
+//! Tunable settings for Peer Node
+
+// importing necessary packages
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use gossip_protocol::{NodeKey, Transport, TransportMode};
+
+// importing necessary files
+use crate::dissemination::DisseminationStrategy;
+use crate::failure_detector::{FailureDetector, FailureDetectorKind, FixedTimeout, PhiAccrual};
+
+// Settings shared by every peer, read from `settings.txt`.
+#[derive(Clone, Debug)]
+pub struct PeerConfig {
+    pub keys_dir: String, // directory holding the signing key of every peer.
+    pub transport: TransportMode, // plain TCP or Noise encrypted connections.
+    pub trusted_keys: Vec<PathBuf>, // directories of the node IDs the Noise transport accepts.
+    pub join_token: Option<String>, // token presented to the seeds when joining.
+    pub dedup_retention: Duration, // how long a seen gossip message is remembered.
+    pub dedup_capacity: usize, // most gossip messages remembered at once.
+    pub gossip_ttl: u32, // times a gossip message may be forwarded after leaving its origin.
+    pub delivery_log_capacity: usize, // most deliveries kept for the spread statistics.
+    pub dissemination: DisseminationStrategy, // how gossip messages are pushed to neighbours.
+    pub anti_entropy_interval: Duration, // time between two digests sent to a random neighbour.
+    pub anti_entropy_digest_size: usize, // most message IDs listed in one digest.
+    pub anti_entropy_push_limit: usize, // most messages pushed in answer to one digest.
+    pub graft_timeout: Duration, // how long plumtree waits for an announced message before grafting.
+    pub liveness_interval: Duration, // time between two liveness requests to a neighbour.
+    pub liveness_sweep: Duration, // time between two checks for dead neighbours.
+    pub pool_idle_timeout: Duration, // how long a pooled stream may sit idle before it is closed.
+    pub failure_detector: FailureDetectorKind, // how a neighbour is found dead.
+    pub liveness_timeout: Duration, // silence after which the fixed detector declares a neighbour dead.
+    pub phi_threshold: f64, // suspicion level above which the phi detector declares a neighbour dead.
+    pub phi_window: usize, // most reply intervals the phi detector keeps per neighbour.
+    pub phi_min_std_dev: Duration, // lower bound of the reply interval deviation used by the phi detector.
+    pub phi_acceptable_pause: Duration, // delay the phi detector tolerates on top of the mean interval.
+    pub indirect_probes: usize, // neighbours asked to probe a suspected neighbour.
+    pub suspicion_timeout: Duration, // how long a neighbour stays suspected before it is confirmed dead.
+    pub dead_node_quarantine: Duration, // how long a neighbour declared dead isn't picked again as a neighbour.
+    pub membership_retransmits: u32, // times each membership update is piggybacked.
+    pub membership_piggyback: usize, // most membership updates piggybacked on one message.
+    pub hlc_max_drift: Duration, // how far ahead of the local clock a received gossip timestamp may be.
+}
+
+impl Default for PeerConfig {
+    fn default() -> Self {
+        PeerConfig {
+            keys_dir: "./keys".to_string(),
+            transport: TransportMode::Plain,
+            trusted_keys: vec![PathBuf::from("./keys")],
+            join_token: None,
+            dedup_retention: Duration::from_secs(5 * 60),
+            dedup_capacity: 10_000,
+            gossip_ttl: 5,
+            delivery_log_capacity: 10_000,
+            dissemination: DisseminationStrategy::Flood,
+            anti_entropy_interval: Duration::from_secs(10),
+            anti_entropy_digest_size: 256,
+            anti_entropy_push_limit: 64,
+            graft_timeout: Duration::from_secs(1),
+            liveness_interval: Duration::from_secs(13),
+            liveness_sweep: Duration::from_secs(14),
+            pool_idle_timeout: Duration::from_secs(60),
+            failure_detector: FailureDetectorKind::FixedTimeout,
+            liveness_timeout: Duration::from_secs(39),
+            phi_threshold: 8.0,
+            phi_window: 100,
+            phi_min_std_dev: Duration::from_secs(2),
+            phi_acceptable_pause: Duration::from_secs(10),
+            indirect_probes: 3,
+            suspicion_timeout: Duration::from_secs(13),
+            dead_node_quarantine: Duration::from_secs(60),
+            membership_retransmits: 6,
+            membership_piggyback: 8,
+            hlc_max_drift: Duration::from_secs(60),
+        }
+    }
+}
+
+impl PeerConfig {
+    // Builds the config from parsed settings, missing or invalid keys keep their default.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Self {
+        let default = PeerConfig::default();
+        PeerConfig {
+            keys_dir: settings.get("keys_dir").cloned().unwrap_or(default.keys_dir),
+            transport: parse_or(settings, "transport", default.transport),
+            // a comma separated list of directories.
+            trusted_keys: settings.get("trusted_keys")
+                .map(|dirs| dirs.split(',').map(PathBuf::from).collect())
+                .unwrap_or(default.trusted_keys),
+            join_token: settings.get("join_token").cloned(),
+            dedup_retention: Duration::from_secs(
+                parse_or(settings, "dedup_retention_secs", default.dedup_retention.as_secs())),
+            dedup_capacity: parse_or(settings, "dedup_capacity", default.dedup_capacity),
+            gossip_ttl: parse_or(settings, "gossip_ttl", default.gossip_ttl),
+            delivery_log_capacity: parse_or(settings, "delivery_log_capacity", default.delivery_log_capacity),
+            dissemination: parse_or(settings, "dissemination", default.dissemination),
+            anti_entropy_interval: Duration::from_secs(
+                parse_or(settings, "anti_entropy_interval_secs", default.anti_entropy_interval.as_secs())),
+            anti_entropy_digest_size: parse_or(settings, "anti_entropy_digest_size", default.anti_entropy_digest_size),
+            anti_entropy_push_limit: parse_or(settings, "anti_entropy_push_limit", default.anti_entropy_push_limit),
+            graft_timeout: Duration::from_secs(
+                parse_or(settings, "graft_timeout_secs", default.graft_timeout.as_secs())),
+            liveness_interval: Duration::from_secs(
+                parse_or(settings, "liveness_interval_secs", default.liveness_interval.as_secs())),
+            liveness_sweep: Duration::from_secs(
+                parse_or(settings, "liveness_sweep_secs", default.liveness_sweep.as_secs())),
+            pool_idle_timeout: Duration::from_secs(
+                parse_or(settings, "pool_idle_timeout_secs", default.pool_idle_timeout.as_secs())),
+            failure_detector: parse_or(settings, "failure_detector", default.failure_detector),
+            liveness_timeout: Duration::from_secs(
+                parse_or(settings, "liveness_timeout_secs", default.liveness_timeout.as_secs())),
+            phi_threshold: parse_or(settings, "phi_threshold", default.phi_threshold),
+            phi_window: parse_or(settings, "phi_window", default.phi_window),
+            phi_min_std_dev: Duration::from_secs(
+                parse_or(settings, "phi_min_std_dev_secs", default.phi_min_std_dev.as_secs())),
+            phi_acceptable_pause: Duration::from_secs(
+                parse_or(settings, "phi_acceptable_pause_secs", default.phi_acceptable_pause.as_secs())),
+            indirect_probes: parse_or(settings, "indirect_probes", default.indirect_probes),
+            suspicion_timeout: Duration::from_secs(
+                parse_or(settings, "suspicion_timeout_secs", default.suspicion_timeout.as_secs())),
+            dead_node_quarantine: Duration::from_secs(
+                parse_or(settings, "dead_node_quarantine_secs", default.dead_node_quarantine.as_secs())),
+            membership_retransmits: parse_or(settings, "membership_retransmits", default.membership_retransmits),
+            membership_piggyback: parse_or(settings, "membership_piggyback", default.membership_piggyback),
+            hlc_max_drift: Duration::from_secs(
+                parse_or(settings, "hlc_max_drift_secs", default.hlc_max_drift.as_secs())),
+        }
+    }
+
+    // Builds the transport used by the peer owning `key`.
+    pub fn transport(&self, key: &NodeKey) -> Transport {
+        match self.transport {
+            TransportMode::Plain => Transport::plain(),
+            TransportMode::Noise => Transport::noise(key, self.trusted_keys.clone()),
+        }
+    }
+
+    // Builds the failure detector watching a peer's neighbours.
+    pub fn failure_detector(&self) -> Box<dyn FailureDetector> {
+        match self.failure_detector {
+            FailureDetectorKind::FixedTimeout => Box::new(FixedTimeout::new(self.liveness_timeout)),
+            FailureDetectorKind::PhiAccrual => Box::new(PhiAccrual::new(self.phi_threshold, self.phi_window,
+                self.phi_min_std_dev, self.phi_acceptable_pause, self.liveness_interval)),
+        }
+    }
+}
+
+// Parses a single setting, falling back to the default when absent or invalid.
+fn parse_or<T: FromStr>(settings: &HashMap<String, String>, key: &str, default: T) -> T {
+    match settings.get(key) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            println!("Invalid value for {}: {}", key, value);
+            default
+        }),
+        None => default,
+    }
+}
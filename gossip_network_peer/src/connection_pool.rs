@@ -0,0 +1,185 @@
+//! Long-lived outgoing connections shared by gossip and liveness traffic
+//!
+//! Streams are one-way: a peer writes to a node over the stream it opened and
+//! reads what the node writes to it on the stream the node opened, so a link
+//! uses one stream per direction and replies are sent through the pool too.
+//! The Connect handshake is the only exchange answered on its own stream, which
+//! is closed once the link is agreed. Streams to nodes that aren't neighbours,
+//! such as probe targets, probe requesters or digest senders, are closed once
+//! they sit idle. Neighbours get a liveness request at every interval, so their
+//! streams stay open.
+
+// importing necessary packages
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::{Duration, Instant, sleep, timeout};
+use gossip_protocol::{Connection, Message, Transport};
+
+// Delay before the first reconnect attempt, doubled after every failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+// Upper bound on the delay between two reconnect attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+// Connection attempts made for a single message before the target is considered unreachable.
+const MAX_ATTEMPTS: u32 = 5;
+// How long messages to an unreachable target are dropped without trying to connect again.
+const UNREACHABLE_COOLDOWN: Duration = MAX_BACKOFF;
+
+// Keeps one stream per target node, each owned by a writer task fed through a channel.
+pub struct ConnectionPool {
+    transport: Arc<Transport>, // opens the connections.
+    idle_timeout: Duration, // how long a writer waits for a message before closing its stream.
+    writers: Mutex<HashMap<String, UnboundedSender<Message>>>, // target address -> its writer task.
+}
+
+impl ConnectionPool {
+    pub fn new(transport: Arc<Transport>, idle_timeout: Duration) -> Self {
+        ConnectionPool { transport, idle_timeout, writers: Mutex::new(HashMap::new()) }
+    }
+
+    // Queues a message for the target, opening its connection on first use.
+    pub fn send(&self, target: &str, message: Message) {
+        let mut writers = self.writers.lock().unwrap();
+        // writers that closed their idle stream are forgotten.
+        writers.retain(|_, writer| !writer.is_closed());
+        let message = match writers.get(target) {
+            Some(writer) => match writer.send(message) {
+                Ok(()) => return,
+                // the writer task is gone, start a new one with the message.
+                Err(err) => err.0,
+            },
+            None => message,
+        };
+        let writer = spawn_writer(self.transport.clone(), target.to_string(), self.idle_timeout);
+        let _ = writer.send(message);
+        writers.insert(target.to_string(), writer);
+    }
+
+    // Closes the connection to a node that is no longer a neighbour.
+    pub fn close(&self, target: &str) {
+        // dropping the sender ends the writer task and its stream.
+        self.writers.lock().unwrap().remove(target);
+    }
+
+    // Closes every connection.
+    pub fn close_all(&self) {
+        self.writers.lock().unwrap().clear();
+    }
+}
+
+// Starts the writer task for a target and returns the channel feeding it.
+fn spawn_writer(transport: Arc<Transport>, target: String, idle_timeout: Duration) -> UnboundedSender<Message> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(run_writer(transport, target, receiver, idle_timeout));
+    sender
+}
+
+// Writes queued messages to the target over a single stream, reconnecting with backoff when it breaks.
+// The writer ends, closing its stream, once no message came for `idle_timeout`.
+async fn run_writer(transport: Arc<Transport>, target: String, mut receiver: UnboundedReceiver<Message>,
+    idle_timeout: Duration) {
+    let mut conn: Option<Connection> = None;
+    let mut backoff = INITIAL_BACKOFF;
+    let mut unreachable_until: Option<Instant> = None;
+
+    loop {
+        let message = match timeout(idle_timeout, receiver.recv()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            // idle, later messages go to a new writer but the ones already queued are still written.
+            Err(_) => {
+                receiver.close();
+                continue;
+            }
+        };
+        // a target that just failed every attempt isn't retried for each queued message.
+        if unreachable_until.is_some_and(|until| Instant::now() < until) {
+            continue;
+        }
+        unreachable_until = None;
+        for attempt in 1..=MAX_ATTEMPTS {
+            if conn.is_none() {
+                match transport.connect(&target).await {
+                    Ok(new_conn) => {
+                        conn = Some(new_conn);
+                        backoff = INITIAL_BACKOFF;
+                    }
+                    Err(err) => {
+                        if attempt == MAX_ATTEMPTS {
+                            eprintln!("Error connecting to {}: {}", target, err);
+                            unreachable_until = Some(Instant::now() + UNREACHABLE_COOLDOWN);
+                            break;
+                        }
+                        sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                        continue;
+                    }
+                }
+            }
+            // a failed write means the connection broke, reconnect and retry the same message.
+            match conn.as_mut().unwrap().send(&message).await {
+                Ok(()) => break,
+                Err(_) => conn = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use tokio::net::TcpListener;
+
+    fn leave(timestamp: u64) -> Message {
+        Message::Leave { addr: "127.0.0.1:9090".to_string(), timestamp }
+    }
+
+    async fn accept(listener: &TcpListener, transport: &Transport) -> Connection {
+        let (stream, _) = listener.accept().await.unwrap();
+        transport.accept(stream).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn idle_streams_are_closed_and_reopened_on_demand() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let transport = Arc::new(Transport::plain());
+        let pool = ConnectionPool::new(transport.clone(), Duration::from_millis(200));
+
+        pool.send(&target, leave(1));
+        let mut conn = accept(&listener, &transport).await;
+        assert_eq!(conn.recv().await.unwrap(), leave(1));
+        // nothing else is sent, so the writer closes the stream.
+        let closed = timeout(Duration::from_secs(2), conn.recv()).await.unwrap();
+        assert_eq!(closed.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        pool.send(&target, leave(2));
+        let mut conn = accept(&listener, &transport).await;
+        assert_eq!(conn.recv().await.unwrap(), leave(2));
+    }
+
+    #[tokio::test]
+    async fn unreachable_targets_are_not_retried_for_every_message() {
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        let transport = Arc::new(Transport::plain());
+        let pool = ConnectionPool::new(transport.clone(), Duration::from_secs(60));
+
+        // every attempt for the first message fails, which takes about 1.5 s of backoff.
+        pool.send(&target, leave(1));
+        sleep(Duration::from_secs(2)).await;
+        let failed_at = Instant::now();
+
+        // the target comes up, but messages sent during the cooldown are dropped without connecting.
+        let listener = TcpListener::bind(&target).await.unwrap();
+        pool.send(&target, leave(2));
+        assert!(timeout(Duration::from_millis(500), listener.accept()).await.is_err());
+
+        sleep(UNREACHABLE_COOLDOWN.saturating_sub(failed_at.elapsed())).await;
+        pool.send(&target, leave(3));
+        let mut conn = timeout(Duration::from_secs(2), accept(&listener, &transport)).await.unwrap();
+        assert_eq!(conn.recv().await.unwrap(), leave(3));
+    }
+}
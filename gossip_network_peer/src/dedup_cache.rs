@@ -0,0 +1,132 @@
+//! Bounded cache of the gossip messages a peer has already seen
+
+// importing necessary packages
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use gossip_protocol::{Message, MessageId};
+
+// Remembers messages for a limited time and up to a limited count, oldest first out.
+// The messages are kept so they can be sent to neighbours that missed them.
+pub struct DedupCache {
+    retention: Duration, // how long a message is remembered.
+    capacity: usize, // most messages remembered at once.
+    messages: HashMap<MessageId, Message>, // messages currently remembered.
+    order: VecDeque<(Instant, MessageId)>, // IDs with the time they were seen, oldest first.
+}
+
+impl DedupCache {
+    pub fn new(retention: Duration, capacity: usize) -> Self {
+        DedupCache { retention, capacity, messages: HashMap::new(), order: VecDeque::new() }
+    }
+
+    // Checks whether a message was seen within the retention period.
+    pub fn contains(&mut self, id: &MessageId) -> bool {
+        self.expire();
+        self.messages.contains_key(id)
+    }
+
+    // Returns a remembered message.
+    pub fn get(&self, id: &MessageId) -> Option<&Message> {
+        self.messages.get(id)
+    }
+
+    // Remembers a gossip message, returns false if it was already seen.
+    pub fn insert(&mut self, message: Message) -> bool {
+        self.expire();
+        let Some(id) = message.gossip_id() else {
+            return false;
+        };
+        if self.messages.contains_key(&id) {
+            return false;
+        }
+        self.messages.insert(id.clone(), message);
+        self.order.push_back((Instant::now(), id));
+        // the oldest messages make room once the cache is full.
+        while self.order.len() > self.capacity {
+            if let Some((_, oldest)) = self.order.pop_front() {
+                self.messages.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    // Returns the IDs of every message remembered, newest first.
+    pub fn ids(&mut self) -> Vec<MessageId> {
+        self.expire();
+        self.order.iter().rev().map(|(_, id)| id.clone()).collect()
+    }
+
+    // Forgets the messages seen longer ago than the retention period.
+    fn expire(&mut self) {
+        while let Some((seen_at, _)) = self.order.front() {
+            if seen_at.elapsed() < self.retention {
+                break;
+            }
+            if let Some((_, id)) = self.order.pop_front() {
+                self.messages.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+    use gossip_protocol::{HlcTimestamp, NodeKey};
+
+    // Signs `count` gossip messages from a single origin, numbered from 1.
+    fn gossip(count: u64) -> Vec<Message> {
+        let dir = tempfile::tempdir().unwrap();
+        let key = NodeKey::load_or_generate(&dir.path().join("peer.key")).unwrap();
+        (1..=count)
+            .map(|seq| key.sign_gossip(HlcTimestamp::default(), "127.0.0.1:9090".to_string(),
+                seq, format!("message #{}", seq), 5))
+            .collect()
+    }
+
+    fn id(message: &Message) -> MessageId {
+        message.gossip_id().unwrap()
+    }
+
+    #[test]
+    fn the_oldest_messages_are_evicted_at_capacity() {
+        let mut cache = DedupCache::new(Duration::from_secs(60), 2);
+        let messages = gossip(3);
+        for message in &messages {
+            assert!(cache.insert(message.clone()));
+        }
+        assert!(!cache.contains(&id(&messages[0])));
+        assert!(cache.get(&id(&messages[0])).is_none());
+        assert_eq!(cache.ids(), vec![id(&messages[2]), id(&messages[1])]);
+    }
+
+    #[test]
+    fn messages_are_forgotten_after_the_retention_time() {
+        let mut cache = DedupCache::new(Duration::from_millis(50), 10);
+        let messages = gossip(2);
+        assert!(cache.insert(messages[0].clone()));
+        sleep(Duration::from_millis(100));
+        assert!(cache.insert(messages[1].clone()));
+        assert!(!cache.contains(&id(&messages[0])));
+        assert!(cache.contains(&id(&messages[1])));
+        assert_eq!(cache.ids(), vec![id(&messages[1])]);
+        // an expired message is accepted again as a new one.
+        assert!(cache.insert(messages[0].clone()));
+    }
+
+    #[test]
+    fn a_duplicate_is_refused_and_keeps_the_first_copy() {
+        let mut cache = DedupCache::new(Duration::from_secs(60), 10);
+        let message = gossip(1).remove(0);
+        assert!(cache.insert(message.clone()));
+        let mut copy = message.clone();
+        if let Message::Gossip { sender, hops, .. } = &mut copy {
+            *sender = "127.0.0.1:9091".to_string();
+            *hops = 2;
+        }
+        assert!(!cache.insert(copy));
+        assert_eq!(cache.get(&id(&message)), Some(&message));
+        assert_eq!(cache.ids().len(), 1);
+    }
+}
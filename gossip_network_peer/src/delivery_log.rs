@@ -0,0 +1,54 @@
+//! Record of the gossip messages delivered to a peer, used to study how far messages spread
+
+// importing necessary packages
+use std::collections::VecDeque;
+use gossip_protocol::{HlcTimestamp, MessageId};
+
+// One gossip message as it was delivered.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub id: MessageId,
+    pub origin: String, // address of the peer that created the message.
+    pub hops: u32, // links the message crossed before it was delivered.
+    pub hlc: HlcTimestamp, // hybrid logical clock of the origin when it created the message.
+    pub delivered_at: HlcTimestamp, // hybrid logical clock of this peer when the message was delivered.
+}
+
+// The most recent deliveries, the oldest are dropped once `capacity` is reached.
+pub struct DeliveryLog {
+    capacity: usize,
+    deliveries: VecDeque<Delivery>,
+}
+
+impl DeliveryLog {
+    pub fn new(capacity: usize) -> Self {
+        DeliveryLog { capacity, deliveries: VecDeque::new() }
+    }
+
+    // Records a delivery.
+    pub fn record(&mut self, delivery: Delivery) {
+        if self.deliveries.len() >= self.capacity {
+            self.deliveries.pop_front();
+        }
+        self.deliveries.push_back(delivery);
+    }
+
+    // Returns the deliveries ordered by the hybrid logical clock they were delivered at.
+    // Merging the logs of several peers in this order gives a global order that respects causality.
+    pub fn ordered(&self) -> Vec<&Delivery> {
+        let mut deliveries: Vec<&Delivery> = self.deliveries.iter().collect();
+        deliveries.sort_by_key(|delivery| delivery.delivered_at);
+        deliveries
+    }
+
+    // Summarises the number of deliveries and how many hops they took.
+    pub fn summary(&self) -> String {
+        if self.deliveries.is_empty() {
+            return "no messages delivered".to_string();
+        }
+        let total_hops: u64 = self.deliveries.iter().map(|delivery| delivery.hops as u64).sum();
+        let max_hops = self.deliveries.iter().map(|delivery| delivery.hops).max().unwrap_or(0);
+        format!("{} messages delivered, {:.2} hops on average, {} at most",
+            self.deliveries.len(), total_hops as f64 / self.deliveries.len() as f64, max_hops)
+    }
+}
@@ -0,0 +1,149 @@
+//! Strategies deciding which neighbours a gossip message is pushed to
+
+// importing necessary packages
+use std::collections::HashSet;
+use std::str::FromStr;
+use rand::Rng;
+
+// importing necessary files
+use crate::utils::select_k_nodes;
+
+// How a peer spreads gossip messages to its neighbours.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisseminationStrategy {
+    Flood, // send every message to every neighbour.
+    Fanout(usize), // send every message to k random neighbours.
+    Probabilistic(f64), // forward a received message to every neighbour with probability p.
+    Plumtree, // push every message along a spanning tree, announce it on the other links.
+}
+
+impl FromStr for DisseminationStrategy {
+    type Err = String;
+
+    // Parses `flood`, `fanout:<k>`, `probabilistic:<p>` or `plumtree`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid dissemination strategy {}", value);
+        match value.split_once(':') {
+            None if value == "flood" => Ok(DisseminationStrategy::Flood),
+            None if value == "plumtree" => Ok(DisseminationStrategy::Plumtree),
+            Some(("fanout", k)) => k.parse().map(DisseminationStrategy::Fanout).map_err(|_| invalid()),
+            Some(("probabilistic", p)) => match p.parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(DisseminationStrategy::Probabilistic(p)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl DisseminationStrategy {
+    // Picks the neighbours a message is sent to, `originated` is true for the peer's own messages.
+    // Plumtree is given its eager links and sends to all of them.
+    pub fn targets(&self, neighbours: &HashSet<String>, originated: bool) -> Vec<String> {
+        let neighbours: Vec<String> = neighbours.iter().cloned().collect();
+        match *self {
+            DisseminationStrategy::Flood | DisseminationStrategy::Plumtree => neighbours,
+            DisseminationStrategy::Fanout(k) => {
+                let k = k.min(neighbours.len());
+                select_k_nodes(neighbours, k)
+            }
+            // a peer always sends its own messages, only forwarding is left to chance.
+            DisseminationStrategy::Probabilistic(p) => {
+                if originated || rand::thread_rng().gen_bool(p) {
+                    neighbours
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use crate::config::PeerConfig;
+    use crate::tokio_peer::TARGET_DEGREE;
+
+    const NODES: usize = 50;
+    const MESSAGES: usize = 200;
+
+    // Random graph built the way peers link: every node asks TARGET_DEGREE random others,
+    // plus a ring so it is connected. Links are symmetric.
+    fn random_graph() -> Vec<HashSet<String>> {
+        let names: Vec<String> = (0..NODES).map(|node| format!("node{}", node)).collect();
+        let mut graph = vec![HashSet::new(); NODES];
+        for node in 0..NODES {
+            let others: Vec<String> = names.iter().filter(|name| **name != names[node]).cloned().collect();
+            let ring = names[(node + 1) % NODES].clone();
+            for other in select_k_nodes(others, TARGET_DEGREE).into_iter().chain([ring]) {
+                let other_index: usize = other["node".len()..].parse().unwrap();
+                graph[node].insert(other);
+                graph[other_index].insert(names[node].clone());
+            }
+        }
+        graph
+    }
+
+    // Copies sent and nodes reached by one message, pushed the way `Peer::disseminate` does
+    // with the other strategies: every node forwards its first copy while the TTL lasts.
+    fn spread(graph: &[HashSet<String>], strategy: DisseminationStrategy, origin: usize, ttl: u32) -> (usize, usize) {
+        let index = |name: &str| name["node".len()..].parse::<usize>().unwrap();
+        let mut reached = HashSet::from([origin]);
+        let mut copies = 0;
+        let mut in_flight: VecDeque<(usize, u32)> = strategy.targets(&graph[origin], true)
+            .iter()
+            .map(|target| (index(target), ttl))
+            .collect();
+        while let Some((node, ttl)) = in_flight.pop_front() {
+            copies += 1;
+            if !reached.insert(node) || ttl == 0 {
+                continue;
+            }
+            for target in strategy.targets(&graph[node], false) {
+                in_flight.push_back((index(&target), ttl - 1));
+            }
+        }
+        (reached.len(), copies)
+    }
+
+    #[test]
+    fn strategies_trade_delivery_for_copies_sent() {
+        let graph = random_graph();
+        let ttl = PeerConfig::default().gossip_ttl;
+        let strategies = [
+            DisseminationStrategy::Flood,
+            DisseminationStrategy::Fanout(2),
+            DisseminationStrategy::Fanout(3),
+            DisseminationStrategy::Probabilistic(0.5),
+            DisseminationStrategy::Probabilistic(0.8),
+        ];
+        let mut results = HashMap::new();
+        for strategy in strategies {
+            let (mut reached, mut copies) = (0, 0);
+            for message in 0..MESSAGES {
+                let (message_reached, message_copies) = spread(&graph, strategy, message % NODES, ttl);
+                reached += message_reached;
+                copies += message_copies;
+            }
+            let delivery_ratio = reached as f64 / (NODES * MESSAGES) as f64;
+            let copies_per_message = copies as f64 / MESSAGES as f64;
+            println!("{:?}: delivery ratio {:.3}, {:.1} copies sent per message", strategy, delivery_ratio, copies_per_message);
+            results.insert(format!("{:?}", strategy), (delivery_ratio, copies_per_message));
+        }
+
+        // flooding reaches every node, at the highest cost.
+        let (flood_ratio, flood_copies) = results["Flood"];
+        assert_eq!(flood_ratio, 1.0);
+        for (strategy, (ratio, copies)) in &results {
+            assert!(*ratio > 0.0 && *ratio <= flood_ratio, "{}: delivery ratio {}", strategy, ratio);
+            assert!(*copies <= flood_copies, "{}: {} copies per message", strategy, copies);
+        }
+        // sending to more neighbours costs more and reaches more nodes.
+        assert!(results["Fanout(2)"].1 < results["Fanout(3)"].1);
+        assert!(results["Fanout(2)"].0 <= results["Fanout(3)"].0);
+        assert!(results["Probabilistic(0.5)"].1 < results["Probabilistic(0.8)"].1);
+        assert!(results["Probabilistic(0.5)"].0 <= results["Probabilistic(0.8)"].0);
+    }
+}
@@ -0,0 +1,196 @@
+//! Failure detectors deciding when a neighbour that stopped answering liveness requests is dead
+
+// importing necessary packages
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// Tracks the liveness replies of the watched neighbours.
+pub trait FailureDetector: Send + Sync {
+    // Starts watching a node, counting from now as if it had just replied.
+    fn watch(&mut self, node: &str, now: Instant);
+
+    // Records a liveness reply, ignored for nodes that aren't watched.
+    fn heartbeat(&mut self, node: &str, now: Instant);
+
+    // Stops watching a node.
+    fn forget(&mut self, node: &str);
+
+    // Returns the watched nodes considered dead at `now`.
+    fn failed(&self, now: Instant) -> Vec<String>;
+}
+
+// Failure detectors a peer can be configured with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureDetectorKind {
+    FixedTimeout, // dead once no reply arrived for a fixed time.
+    PhiAccrual, // dead once the suspicion level computed from past replies crosses a threshold.
+}
+
+impl FromStr for FailureDetectorKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "fixed" => Ok(FailureDetectorKind::FixedTimeout),
+            "phi" => Ok(FailureDetectorKind::PhiAccrual),
+            _ => Err(format!("unknown failure detector {}", value)),
+        }
+    }
+}
+
+// Declares a node dead when its last reply is older than `timeout`.
+pub struct FixedTimeout {
+    timeout: Duration,
+    last_heard: HashMap<String, Instant>, // time of the last reply from each watched node.
+}
+
+impl FixedTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        FixedTimeout { timeout, last_heard: HashMap::new() }
+    }
+}
+
+impl FailureDetector for FixedTimeout {
+    fn watch(&mut self, node: &str, now: Instant) {
+        self.last_heard.insert(node.to_string(), now);
+    }
+
+    fn heartbeat(&mut self, node: &str, now: Instant) {
+        if let Some(last_heard) = self.last_heard.get_mut(node) {
+            *last_heard = now;
+        }
+    }
+
+    fn forget(&mut self, node: &str) {
+        self.last_heard.remove(node);
+    }
+
+    fn failed(&self, now: Instant) -> Vec<String> {
+        self.last_heard.iter()
+            .filter(|(_, last_heard)| now.duration_since(**last_heard) > self.timeout)
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+}
+
+// Phi accrual failure detector (Hayashibara et al.). The intervals between replies are assumed
+// to be normally distributed, phi is -log10 of the probability that the next reply is still
+// to come after the time elapsed since the last one.
+pub struct PhiAccrual {
+    threshold: f64, // phi above which a node is dead.
+    window: usize, // most reply intervals kept per node.
+    min_std_dev: Duration, // lower bound of the standard deviation, so steady replies don't make phi jumpy.
+    acceptable_pause: Duration, // delay added to the mean interval before suspicion builds up.
+    first_interval: Duration, // interval assumed before the first reply arrives.
+    arrivals: HashMap<String, ArrivalWindow>,
+}
+
+// Recent reply history of one node.
+struct ArrivalWindow {
+    last_heard: Instant,
+    replied: bool, // whether a reply arrived yet, the first one only restarts the clock.
+    intervals: VecDeque<f64>, // intervals between replies in milliseconds, oldest first.
+}
+
+impl PhiAccrual {
+    pub fn new(threshold: f64, window: usize, min_std_dev: Duration, acceptable_pause: Duration,
+        first_interval: Duration) -> Self {
+        PhiAccrual { threshold, window: window.max(1), min_std_dev, acceptable_pause, first_interval, arrivals: HashMap::new() }
+    }
+
+    // Suspicion level of a node at `now`.
+    fn phi(&self, arrivals: &ArrivalWindow, now: Instant) -> f64 {
+        let count = arrivals.intervals.len() as f64;
+        let mean = arrivals.intervals.iter().sum::<f64>() / count;
+        let variance = arrivals.intervals.iter().map(|interval| (interval - mean).powi(2)).sum::<f64>() / count;
+        let std_dev = variance.sqrt().max(self.min_std_dev.as_secs_f64() * 1000.0);
+        let mean = mean + self.acceptable_pause.as_secs_f64() * 1000.0;
+
+        let elapsed = now.duration_since(arrivals.last_heard).as_secs_f64() * 1000.0;
+        // logistic approximation of the normal cumulative distribution.
+        let y = (elapsed - mean) / std_dev;
+        let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+        if elapsed > mean {
+            -(e / (1.0 + e)).log10()
+        } else {
+            -(1.0 - 1.0 / (1.0 + e)).log10()
+        }
+    }
+}
+
+impl FailureDetector for PhiAccrual {
+    fn watch(&mut self, node: &str, now: Instant) {
+        let intervals = VecDeque::from([self.first_interval.as_secs_f64() * 1000.0]);
+        self.arrivals.insert(node.to_string(), ArrivalWindow { last_heard: now, replied: false, intervals });
+    }
+
+    fn heartbeat(&mut self, node: &str, now: Instant) {
+        let Some(arrivals) = self.arrivals.get_mut(node) else {
+            return;
+        };
+        let interval = now.duration_since(arrivals.last_heard).as_secs_f64() * 1000.0;
+        arrivals.last_heard = now;
+        // the first request goes out as soon as the link is made, so its reply says nothing about the interval.
+        if !arrivals.replied {
+            arrivals.replied = true;
+            return;
+        }
+        arrivals.intervals.push_back(interval);
+        if arrivals.intervals.len() > self.window {
+            arrivals.intervals.pop_front();
+        }
+    }
+
+    fn forget(&mut self, node: &str) {
+        self.arrivals.remove(node);
+    }
+
+    fn failed(&self, now: Instant) -> Vec<String> {
+        self.arrivals.iter()
+            .filter(|(_, arrivals)| self.phi(arrivals, now) > self.threshold)
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn fixed_timeout_keeps_working_past_the_hour() {
+        let mut detector = FixedTimeout::new(Duration::from_secs(15));
+        let start = Instant::now();
+        detector.watch("127.0.0.1:9090", start);
+        detector.watch("127.0.0.1:9091", start);
+        // one node keeps replying every 10 seconds for more than an hour, the other falls silent.
+        let mut now = start;
+        while now < start + HOUR + Duration::from_secs(60) {
+            now += Duration::from_secs(10);
+            detector.heartbeat("127.0.0.1:9090", now);
+            let failed = detector.failed(now);
+            assert!(!failed.contains(&"127.0.0.1:9090".to_string()), "declared dead {:?} after start", now - start);
+        }
+        assert_eq!(detector.failed(now), vec!["127.0.0.1:9091".to_string()]);
+        // the silent node is dead however long ago it last replied.
+        assert_eq!(detector.failed(start + 2 * HOUR + Duration::from_secs(1)).len(), 2);
+    }
+
+    #[test]
+    fn phi_accrual_keeps_working_past_the_hour() {
+        let mut detector = PhiAccrual::new(8.0, 100, Duration::from_millis(500), Duration::ZERO, Duration::from_secs(10));
+        let start = Instant::now();
+        detector.watch("127.0.0.1:9090", start);
+        let mut now = start;
+        while now < start + HOUR + Duration::from_secs(60) {
+            now += Duration::from_secs(10);
+            detector.heartbeat("127.0.0.1:9090", now);
+            assert!(detector.failed(now + Duration::from_secs(5)).is_empty());
+        }
+        // a reply a minute late after an hour of steady ones makes the node dead.
+        assert_eq!(detector.failed(now + Duration::from_secs(60)), vec!["127.0.0.1:9090".to_string()]);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader};
 
@@ -11,7 +12,7 @@ pub fn read_file(file_path: &str) -> Result<(Vec<String>, Vec<String>), io::Erro
 
     for line in reader.lines() {
         let line = line?;
-        let parts: Vec<&str> = line.trim().split_whitespace().collect();
+        let parts: Vec<&str> = line.split_whitespace().collect();
 
         if parts.len() == 2 {
             let ip_address = parts[0].to_string();
@@ -24,4 +25,24 @@ pub fn read_file(file_path: &str) -> Result<(Vec<String>, Vec<String>), io::Erro
     }
 
     Ok((ips, ports))
-}
\ No newline at end of file
+}
+
+// logic for reading `key value` settings from a file.
+pub fn read_settings(file_path: &str) -> Result<HashMap<String, String>, io::Error> {
+    let file = File::open(file_path)?;
+    let reader = BufReader::new(file);
+
+    let mut settings = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if parts.len() == 2 {
+            settings.insert(parts[0].to_string(), parts[1].to_string());
+        } else if !parts.is_empty() {
+            println!("Invalid line format: {}", line);
+        }
+    }
+
+    Ok(settings)
+}
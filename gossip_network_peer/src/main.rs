@@ -1,18 +1,29 @@
-/// Main code for Peer Node
+//! Main code for Peer Node
 
 // Importing necessary files.
 mod file_reader;
 mod tokio_peer;
 mod network;
 mod utils;
+mod connection_pool;
+mod config;
+mod dedup_cache;
+mod delivery_log;
+mod dissemination;
+mod plumtree;
+mod failure_detector;
+mod membership;
 
 // importing necessary modules
 use tokio_peer::Peer;
-use network::{check_liveness, spawn_listener, broadcast_message, idle_listener};
+use config::PeerConfig;
+use network::{connect_neighbours, find_neighbour_candidates, spawn_listener, idle_listener, start_anti_entropy};
 use utils::{get_ips, select_k_nodes};
 use tokio::time::{Duration, sleep};
 use tokio::sync::Mutex;
+use std::path::Path;
 use std::sync::Arc;
+use gossip_protocol::{keys, NodeKey};
 
 
 #[tokio::main]
@@ -21,6 +32,17 @@ async fn main() {
     let peer_ips_path = "./src/peer_addr.txt";
     let seed_ips_path = "./src/config.txt";
 
+    // Reads the tunable settings shared by every peer from the settings.txt file
+    let settings_path = "./src/settings.txt";
+    let config = match file_reader::read_settings(settings_path) {
+        Ok(settings) => PeerConfig::from_settings(&settings),
+        Err(err) => {
+            println!("Using default settings, could not read {}: {}", settings_path, err);
+            PeerConfig::default()
+        }
+    };
+    let keys_dir = Path::new(&config.keys_dir);
+
     // Reading the IP and Ports for Seed and Peers.
     let local_addresses = match get_ips(peer_ips_path) {
         Ok(local_addresses) => local_addresses,
@@ -40,39 +62,23 @@ async fn main() {
     // Selecting K seeds for each Peer
     let tot_seeds = seed_nodes.len();
     let mut peers: Vec<Arc<Mutex<Peer>>> = Vec::new();
-    let mut itr = 1;
-    for local_address in local_addresses {
+    for (index, local_address) in local_addresses.into_iter().enumerate() {
+        let itr = index as i32 + 1;
 
         let selected_seeds: Vec<String> = select_k_nodes(seed_nodes.clone(), tot_seeds/2+1);
         println!("Peer@{}: Selected seeds: {:?}", local_address, selected_seeds);
-        let peer = Arc::new(Mutex::new(Peer::new(itr, local_address, selected_seeds)));
-
-        // Join seed nodes using a lock guard.
-        {
-            let mut peer_guard = peer.lock().await;
-            peer_guard.join_seed_nodes().await;
-        }
-        peers.push(peer);
-        itr += 1;
-    }
-
-    // Select 4 distinct Peers for each Peer
-    let tot_distinct_nodes = 4;
-    for peer in &mut peers{
-        let mut peer_guard = peer.lock().await;
-        {
-            peer_guard.query_connected_nodes().await;
-            println!("Peer@{}: Peer nodes from Seeds: {:?}",
-                peer_guard.local_addr, peer_guard.connected_nodes);
-            // println!("Connected nodes of {}: {:?}", peer_guard.local_addr, peer_guard.connected_nodes);   
-            if peer_guard.connected_nodes.len() > tot_distinct_nodes {
-                let selected_nodes: Vec<_> = peer_guard.connected_nodes.iter().cloned().collect();
-                let selected_nodes: Vec<_> = select_k_nodes(selected_nodes, tot_distinct_nodes);
-                peer_guard.connected_nodes = selected_nodes.into_iter().collect();
+        // each peer keeps its key across restarts, so its node ID stays the same.
+        let key_path = tokio_peer::key_path(keys_dir, &local_address);
+        let key = match NodeKey::load_or_generate(&key_path) {
+            Ok(key) => key,
+            Err(err) => {
+                eprintln!("Error loading key {}: {}", key_path.display(), err);
+                std::process::exit(1);
             }
-            println!("Peer@{}: Selected peer nodes: {:?}",
-                peer_guard.local_addr, peer_guard.connected_nodes);   
-        }
+        };
+        println!("Peer@{}: Node ID {}", local_address, keys::node_id_hex(&key.node_id()));
+        let peer = Arc::new(Mutex::new(Peer::new(itr, local_address, selected_seeds, config.clone(), key)));
+        peers.push(peer);
     }
 
     // Spawn Listener for each peer.
@@ -90,59 +96,80 @@ async fn main() {
         // Spawn normal istener for other peers.
         let listener_handle = tokio::spawn(spawn_listener(peer.clone()));
         listeners.push(listener_handle);
+        // periodically exchange digests with a neighbour to recover missed messages.
+        tokio::spawn(start_anti_entropy(peer.clone()));
     }
 
     sleep(Duration::from_secs(2)).await;
 
-    //checks for liveness of peers connected to each respective peer.
-    let peers_clone = peers.clone();
-    for peer in &peers_clone {
-        // Clone the peer and connected node for a shared reference.
-        let peer_clone = Arc::clone(peer);
-        let connected_nodes = peer_clone.lock().await.connected_nodes.clone();
-        for connected_node in connected_nodes {
-            let target_node = connected_node.clone();
-            let peer_clone = Arc::clone(&peer);
-
-            // Spawn a task for each pair of peer and connected node
-            tokio::spawn(async move {
-                check_liveness(peer_clone, target_node).await;
-            });
-        }
+    // Join seed nodes using a lock guard, once every listener is up so the seeds can check the peers' addresses.
+    for peer in &peers {
+        let mut peer_guard = peer.lock().await;
+        peer_guard.join_seed_nodes().await;
     }
+
+    // Link each Peer with 4 distinct Peers that agree to the link.
+    // Liveness checks are started for every agreed link on both ends.
+    for peer in &peers {
+        let candidates = find_neighbour_candidates(peer).await;
+        println!("Peer@{}: Peer nodes from Seeds: {:?}", peer.lock().await.local_addr, candidates);
+        connect_neighbours(Arc::clone(peer), candidates).await;
+        let peer_guard = peer.lock().await;
+        println!("Peer@{}: Selected peer nodes: {:?}",
+            peer_guard.local_addr, peer_guard.connected_nodes);
+    }
+
     // Broadcasts the gossip messages to all peers every 5 seconds for 10 times.
-    for _itr in 1..11 {
-        let mut handles = vec![];
+    let peers_clone = peers.clone();
+    let gossip_rounds = tokio::spawn(async move {
+        for _itr in 1..11 {
+            let mut handles = vec![];
 
-        for peer in peers.iter() {
-            let peer_clone = Arc::clone(&peer);
+            for peer in peers_clone.iter() {
+                let peer_clone = Arc::clone(peer);
 
-            let handle = tokio::spawn(async move {
+                let handle = tokio::spawn(async move {
 
-                // wait to obtain lock on the shared peer reference.
-                let mut peer_guard = peer_clone.lock().await;
-                let gossip = format!("Hello, this is peer @{}!", peer_guard.local_addr);
-                // Add your own message to the message list.
-                peer_guard.message_list.insert(gossip.to_string());
-                let message = format!("{}|{}|{}", peer_guard.elapsed_time(), peer_guard.local_addr, gossip);
-                // broadcast the message
-                broadcast_message(&peer_guard.connected_nodes, message.clone()).await;
-            });
+                    // wait to obtain lock on the shared peer reference.
+                    let mut peer_guard = peer_clone.lock().await;
+                    let gossip = format!("Hello, this is peer @{}!", peer_guard.local_addr);
+                    // sign the message so receivers can check it really comes from this peer.
+                    let message = peer_guard.originate_gossip(gossip);
+                    // push the message to the neighbours picked by the dissemination strategy.
+                    peer_guard.disseminate(message, None);
+                });
 
-            handles.push(handle);
-        }
+                handles.push(handle);
+            }
 
-        // Wait for all tasks to complete before moving to the next iteration
-        for handle in handles {
-            let _ = handle.await;
-        }
+            // Wait for all tasks to complete before moving to the next iteration
+            for handle in handles {
+                let _ = handle.await;
+            }
 
-        // Wait for 5 seconds before broadcasting the next message
-        sleep(Duration::from_secs(5)).await;
-    }
-    
+            // Wait for 5 seconds before broadcasting the next message
+            sleep(Duration::from_secs(5)).await;
+        }
+    });
 
     tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
+    gossip_rounds.abort();
 
-
+    // Leave the network gracefully, the simulated dead node stays silent.
+    for (index, peer) in peers.iter().enumerate() {
+        if index == peers.len() - 1 && peers.len() > 1 {
+            continue;
+        }
+        let mut peer_guard = peer.lock().await;
+        println!("Peer@{}: {}, {} gossip copies sent, {} duplicates received", peer_guard.local_addr,
+            peer_guard.deliveries.summary(), peer_guard.gossip_sent, peer_guard.duplicates_received);
+        println!("Peer@{}: Membership view: {}", peer_guard.local_addr, peer_guard.membership.summary());
+        // the delivery log in HLC order, the logs of every peer can be merged by sorting on it.
+        for delivery in peer_guard.deliveries.ordered() {
+            println!("Peer@{}: Delivered at hlc {}: message #{} from {} created at hlc {}", peer_guard.local_addr,
+                delivery.delivered_at, delivery.id.seq, delivery.origin, delivery.hlc);
+        }
+        // returns once the seeds and neighbours acknowledged, the listeners are still up meanwhile.
+        peer_guard.leave_network().await;
+    }
 }
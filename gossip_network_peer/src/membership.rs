@@ -0,0 +1,105 @@
+//! Membership view the peers keep up to date among themselves by piggybacking updates on their messages
+
+// importing necessary packages
+use std::collections::HashMap;
+use gossip_protocol::{MemberState, MembershipUpdate};
+
+// What a peer knows about the other peers, and the updates it still has to pass on.
+pub struct MembershipView {
+    members: HashMap<String, (MemberState, u64)>, // state and incarnation of every known peer.
+    pending: HashMap<String, (MembershipUpdate, u32)>, // updates to piggyback and how many more times.
+    retransmits: u32, // times each update is piggybacked.
+    max_piggyback: usize, // most updates piggybacked on one message.
+}
+
+impl MembershipView {
+    pub fn new(retransmits: u32, max_piggyback: usize) -> Self {
+        MembershipView { members: HashMap::new(), pending: HashMap::new(), retransmits, max_piggyback }
+    }
+
+    // Adds a peer learned from a seed, assumed alive until an update says otherwise.
+    pub fn discover(&mut self, addr: &str) {
+        self.members.entry(addr.to_string()).or_insert((MemberState::Alive, 0));
+    }
+
+    // Applies an update received or made locally, returns true if it changed the view.
+    // Changes are passed on to the neighbours.
+    pub fn apply(&mut self, update: MembershipUpdate) -> bool {
+        if let Some(&(state, incarnation)) = self.members.get(&update.addr) {
+            if !overrides(&update, state, incarnation) {
+                return false;
+            }
+        }
+        self.members.insert(update.addr.clone(), (update.state, update.incarnation));
+        self.announce(update);
+        true
+    }
+
+    // Queues an update to be piggybacked, without changing the view.
+    pub fn announce(&mut self, update: MembershipUpdate) {
+        if self.retransmits > 0 {
+            self.pending.insert(update.addr.clone(), (update, self.retransmits));
+        }
+    }
+
+    // Returns the incarnation known for a peer, 0 if it is unknown.
+    pub fn incarnation(&self, addr: &str) -> u64 {
+        self.members.get(addr).map_or(0, |(_, incarnation)| *incarnation)
+    }
+
+    // Returns the peers believed to be in the network, suspects included.
+    pub fn live_members(&self) -> Vec<String> {
+        self.members.iter()
+            .filter(|(_, (state, _))| matches!(state, MemberState::Alive | MemberState::Suspect))
+            .map(|(addr, _)| addr.clone())
+            .collect()
+    }
+
+    // Checks whether a peer is believed to be in the network, suspects included.
+    pub fn is_live(&self, addr: &str) -> bool {
+        matches!(self.members.get(addr), Some((MemberState::Alive | MemberState::Suspect, _)))
+    }
+
+    // Picks the updates to piggyback on the next message, the least sent first.
+    pub fn piggyback(&mut self) -> Vec<MembershipUpdate> {
+        let mut pending: Vec<(&String, u32)> = self.pending.iter()
+            .map(|(addr, (_, remaining))| (addr, *remaining))
+            .collect();
+        pending.sort_by_key(|(_, remaining)| std::cmp::Reverse(*remaining));
+        let picked: Vec<String> = pending.into_iter()
+            .take(self.max_piggyback)
+            .map(|(addr, _)| addr.clone())
+            .collect();
+
+        let mut updates = Vec::with_capacity(picked.len());
+        for addr in picked {
+            if let Some((update, remaining)) = self.pending.get_mut(&addr) {
+                updates.push(update.clone());
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.pending.remove(&addr);
+                }
+            }
+        }
+        updates
+    }
+
+    // Counts the known peers in each state.
+    pub fn summary(&self) -> String {
+        let count = |wanted: MemberState| self.members.values().filter(|(state, _)| *state == wanted).count();
+        format!("{} alive, {} suspect, {} dead, {} left", count(MemberState::Alive),
+            count(MemberState::Suspect), count(MemberState::Dead), count(MemberState::Left))
+    }
+}
+
+// Checks whether an update replaces the known state of its peer. A newer incarnation always wins,
+// within one incarnation suspicion beats alive and death or leaving beats both.
+fn overrides(update: &MembershipUpdate, state: MemberState, incarnation: u64) -> bool {
+    let rank = |state: MemberState| match state {
+        MemberState::Alive => 0,
+        MemberState::Suspect => 1,
+        MemberState::Dead | MemberState::Left => 2,
+    };
+    update.incarnation > incarnation
+        || (update.incarnation == incarnation && rank(update.state) > rank(state))
+}
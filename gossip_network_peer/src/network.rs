@@ -1,81 +1,177 @@
-/// Contains the networking logic for Peer node
+//! Contains the networking logic for Peer node
 
 // Importing necessary packages
-use std::collections::{HashSet, HashMap};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::time::{Duration, sleep};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use tokio::net::TcpListener;
+use tokio::time::{Duration, sleep, timeout};
 use tokio::sync::Mutex;
 use std::sync::Arc;
+use std::time::Instant;
+use gossip_protocol::{keys, now_ms, Connection, ConnectionIdentity, MemberState, Message, MessageId, Transport};
 
 // importing necessary files
-use crate::tokio_peer::Peer;
-use crate::utils::parse_and_convert_to_ms;
+use crate::delivery_log::Delivery;
+use crate::dissemination::DisseminationStrategy;
+use crate::tokio_peer::{Peer, MAX_DEGREE, TARGET_DEGREE};
+use crate::utils::select_k_nodes;
 
-//Constants for specific reply
-const LIVENESS_REQUEST: &str = "LIVENESS_REQUEST";
-const LIVENESS_REPLY: &str = "LIVENESS_REPLY";
+// How long to wait for a node to answer a neighbour handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
 
-// This function broadcasts the message to all connected nodes
-pub async fn broadcast_message(connected_nodes: &HashSet<String>, message: String) {
-    for connected_node in connected_nodes {
-        let address = connected_node; 
-        // Create a TCP connection and write the broadcast message to all the peers.
-        match TcpStream::connect(address).await {
-            Ok(mut stream) => {
-                let _ = stream.write_all(message.as_bytes()).await;
-                // println!("Message sent to {}: {}", connected_node, message);
-            }
-            Err(err) => {
-                eprintln!("Error connecting to {}: {}", connected_node, err);
-            }
+
+// Sends liveness requests from a shared peer reference to a target node at the configured interval.
+pub async fn check_liveness(peer: Arc<Mutex<Peer>>, target: String){
+    let interval = peer.lock().await.config.liveness_interval;
+    loop{
+        let mut peer_guard = peer.lock().await;
+        // check if the node is still considered alive or not.
+        if peer_guard.connected_nodes.contains(&target){
+            let message = Message::LivenessRequest {
+                timestamp: now_ms(),
+                addr: peer_guard.local_addr.clone(),
+                updates: Vec::new(),
+            };
+            let message = peer_guard.piggyback(message);
+            // send liveness request
+            peer_guard.pool.send(&target, message);
+            drop(peer_guard);
+        }
+        else{
+            drop(peer_guard);
+            break;
         }
+        // sleep until the next request.
+        sleep(interval).await;
     }
 }
 
-// This function establishes a TCP connection and sends a liveness request.
-pub async fn send_liveness_request(target_node: &String, message: String) {
-    match TcpStream::connect(target_node).await {
-        Ok(mut stream) => {
-            let _ = stream.write_all(message.as_bytes()).await;
-            // println!("Message sent to {}: {}", connected_node, message);
-        }
+// Asks a node to become a neighbour, returns true if it accepted the link.
+pub async fn request_link(transport: &Transport, local_addr: &str, target_node: &str, timestamp: u64) -> bool {
+    let mut conn = match transport.connect(target_node).await {
+        Ok(conn) => conn,
         Err(err) => {
             eprintln!("Error connecting to {}: {}", target_node, err);
+            return false;
         }
+    };
+    let request = Message::Connect { addr: local_addr.to_string(), timestamp };
+    if conn.send(&request).await.is_err() {
+        return false;
     }
-}
-// This function establishes a TCP connection and sends a liveness reply.
-pub async fn send_liveness_reply(target_node: &String, message: String) {
-    match TcpStream::connect(target_node).await {
-        Ok(mut stream) => {
-            let _ = stream.write_all(message.as_bytes()).await;
-            // println!("Message sent to {}: {}", connected_node, message);
+    // wait for the node to agree or refuse the link.
+    match timeout(HANDSHAKE_TIMEOUT, conn.recv()).await {
+        Ok(Ok(Message::Accept { .. })) => true,
+        Ok(Ok(Message::Reject { addr, reason })) => {
+            println!("Peer@{}: Link refused by {}: {}", local_addr, addr, reason);
+            false
         }
-        Err(err) => {
-            eprintln!("Error connecting to {}: {}", target_node, err);
+        _ => false,
+    }
+}
+
+// Links with candidates in random order until the target degree is reached, and checks their liveness.
+pub async fn connect_neighbours(peer: Arc<Mutex<Peer>>, candidates: Vec<String>) {
+    let candidates_len = candidates.len();
+    for node in select_k_nodes(candidates, candidates_len) {
+        // the lock isn't held during the handshake so two peers can link with each other at once.
+        let (transport, local_addr, timestamp) = {
+            let peer_guard = peer.lock().await;
+            if peer_guard.connected_nodes.len() >= TARGET_DEGREE {
+                break;
+            }
+            if peer_guard.connected_nodes.contains(&node) {
+                continue;
+            }
+            (peer_guard.transport.clone(), peer_guard.local_addr.clone(), now_ms())
+        };
+        if request_link(&transport, &local_addr, &node, timestamp).await && peer.lock().await.add_neighbour(node.clone()) {
+            tokio::spawn(check_liveness(peer.clone(), node));
         }
     }
 }
-// Checks for liveness between a shared peer reference and target node every 13 seconds.
-pub async fn check_liveness(peer: Arc<Mutex<Peer>>, target: String){
 
-    loop{
+// Asks the seeds for peers without holding the lock, then returns the candidates for new neighbours.
+pub async fn find_neighbour_candidates(peer: &Arc<Mutex<Peer>>) -> Vec<String> {
+    let (transport, local_addr, seed_nodes) = {
         let peer_guard = peer.lock().await;
-        // check if the node is still considered alive or not.
-        if peer_guard.connected_nodes.contains(&target){
-            let message = format!("{}|{}|{}", LIVENESS_REQUEST, peer_guard.elapsed_time(), peer_guard.local_addr);
-            // send liveness request
-            send_liveness_request(&target, message).await;
-            drop(peer_guard);
+        (peer_guard.transport.clone(), peer_guard.local_addr.clone(), peer_guard.seed_nodes.clone())
+    };
+    let listed = Peer::query_connected_nodes(&transport, &local_addr, &seed_nodes).await;
+    peer.lock().await.neighbour_candidates(listed)
+}
+
+// Picks new neighbours from the seeds until the target degree is restored.
+pub async fn replace_lost_neighbours(peer: Arc<Mutex<Peer>>) {
+    let candidates = find_neighbour_candidates(&peer).await;
+    connect_neighbours(peer.clone(), candidates).await;
+    let peer_guard = peer.lock().await;
+    println!("Peer@{}: Peer nodes after replacement: {:?}", peer_guard.local_addr, peer_guard.connected_nodes);
+}
+
+// Returns the next `size` IDs from `cursor` on, wrapping around, and moves the cursor past them.
+// Successive digests go through every remembered message, and a digest is only shorter than
+// `size` when it lists every one of them.
+fn digest_page(ids: &[MessageId], cursor: &mut usize, size: usize) -> Vec<MessageId> {
+    if ids.is_empty() {
+        *cursor = 0;
+        return Vec::new();
+    }
+    let start = *cursor % ids.len();
+    let page: Vec<MessageId> = ids.iter().cycle().skip(start).take(size.min(ids.len())).cloned().collect();
+    *cursor = (start + page.len()) % ids.len();
+    page
+}
+
+// Sends a digest of the remembered gossip messages to a random neighbour at every interval,
+// so messages either of them missed are exchanged. An interval of 0 turns it off.
+// Each digest lists at most `anti_entropy_digest_size` IDs, the next digest carries on where it stopped,
+// so a peer that was away for a while still catches up over a few rounds.
+pub async fn start_anti_entropy(peer: Arc<Mutex<Peer>>) {
+    let interval = peer.lock().await.config.anti_entropy_interval;
+    if interval.is_zero() {
+        return;
+    }
+    let mut cursor = 0;
+    loop {
+        sleep(interval).await;
+        let mut peer_guard = peer.lock().await;
+        let neighbours: Vec<String> = peer_guard.connected_nodes.iter().cloned().collect();
+        // a peer that lost all its neighbours has nobody to compare with.
+        if neighbours.is_empty() {
+            continue;
         }
-        else{
-            drop(peer_guard);
-            break;
+        let Some(target) = select_k_nodes(neighbours, 1).pop() else {
+            continue;
+        };
+        let digest_size = peer_guard.config.anti_entropy_digest_size;
+        let ids = digest_page(&peer_guard.seen_messages.ids(), &mut cursor, digest_size);
+        let digest = Message::Digest { sender: peer_guard.local_addr.clone(), ids };
+        peer_guard.pool.send(&target, digest);
+    }
+}
+
+// Waits for messages announced by a neighbour to arrive along the tree,
+// grafting the neighbour's link and asking it for the ones that don't.
+async fn graft_missing(peer: Arc<Mutex<Peer>>, announcer: String, ids: Vec<MessageId>) {
+    let graft_timeout = peer.lock().await.config.graft_timeout;
+    sleep(graft_timeout).await;
+    let mut peer_guard = peer.lock().await;
+    let mut missing = Vec::new();
+    for id in ids {
+        peer_guard.tree.settle(&id);
+        if !peer_guard.seen_messages.contains(&id) {
+            missing.push(id);
         }
-        // sleep for 13 secs.
-        sleep(Duration::from_secs(13)).await;
     }
+    // messages announced by a neighbour that is gone are left to anti-entropy.
+    if missing.is_empty() || !peer_guard.connected_nodes.contains(&announcer) {
+        return;
+    }
+    peer_guard.tree.graft(&announcer);
+    println!("Peer@{}: Grafting {} for {} missing messages", peer_guard.local_addr, announcer, missing.len());
+    let graft = Message::Graft { sender: peer_guard.local_addr.clone(), ids: missing };
+    peer_guard.pool.send(&announcer, graft);
 }
 
 // Creates a Listener, checks for liveness and responds to messages
@@ -84,118 +180,339 @@ pub async fn spawn_listener(peer: Arc<Mutex<Peer>>) {
     let peer_guard = peer.lock().await;
     let listener = TcpListener::bind(&peer_guard.local_addr).await.unwrap();
     println!("Peer {}: Listening on {}", peer_guard.peer_no, peer_guard.local_addr);
+    let transport = peer_guard.transport.clone();
     // drop the lock.
     drop(peer_guard);
 
-    // intialize a connection times which tracks when was the last liveness request received from a peer.
-    let connection_times = Arc::new(Mutex::new(HashMap::<String, i32>::new()));
-    for connected_node in &peer.lock().await.connected_nodes {
-        connection_times.lock().await.insert(connected_node.to_string().clone(), 0);
-    }
-
-    // Checks for connection timeout by spawning a thread which loops every 14 seconds.
+    // Checks for dead neighbours by spawning a thread which loops at the configured sweep interval.
     {
-        // create a shared reference from connection times and peer.
-        let connection_times_clones = connection_times.clone();
+        // create a shared reference from peer.
         let peer_clone = peer.clone();
         tokio::spawn(async move {
             // ... existing code within the task
-            sleep(Duration::from_secs(2)).await; // Pause for 1 second
-    
+            sleep(Duration::from_secs(2)).await; // Pause for 2 seconds
+            let sweep_interval = peer_clone.lock().await.config.liveness_sweep;
+
             loop {
-                // Check the condition every second
-                let cur_time = parse_and_convert_to_ms(peer_clone.lock().await.elapsed_time().as_str());
-                let mut nodes_to_remove: Vec<String> = Vec::new();
-                // Aquire lock on connection_times shared reference
-                let mut connection_times_guard = connection_times_clones.lock().await;
-                
-                for (key, value) in connection_times_guard.iter() {
-                    // println!("Key: {}, Value: {}", key, value);
-                    let prev_time: i32 = *value;
-                    // check if the last liveness reply was more than 39 seconds ago.
-                    if cur_time - prev_time >39000{
-
-                        // get a lock on peer reference and remvoe the connection.
-                        let mut peer_guard = peer_clone.lock().await;
-                        peer_guard.connected_nodes.remove(key);
-                        nodes_to_remove.push(key.clone());
-                        peer_guard.declare_node_dead(key.clone()).await;
-                    }
+                // get a lock on peer reference for the whole sweep.
+                let mut peer_guard = peer_clone.lock().await;
+                // neighbours that stopped replying are suspected first, only confirmed deaths are removed.
+                let now = Instant::now();
+                let failed = peer_guard.failure_detector.failed(now);
+                let nodes_to_remove = peer_guard.update_suspects(failed, now);
+
+                for key in &nodes_to_remove {
+                    // remvoe the connection and remove from connection times.
+                    peer_guard.connected_nodes.remove(key);
+                    peer_guard.tree.forget(key);
+                    peer_guard.failure_detector.forget(key);
+                    peer_guard.pool.close(key);
+                    peer_guard.declared_dead.insert(key.clone(), now);
+                }
+                let (transport, local_addr, seed_nodes) =
+                    (peer_guard.transport.clone(), peer_guard.local_addr.clone(), peer_guard.seed_nodes.clone());
+                // drop the guard on peer, the seeds are told without holding it.
+                drop(peer_guard);
+                for key in &nodes_to_remove {
+                    Peer::declare_node_dead(&transport, &local_addr, &seed_nodes, key).await;
                 }
 
-                for key in nodes_to_remove {
-                    // remove from connection times.
-                    connection_times_guard.remove(&key);
+                // find new neighbours for the ones that were lost.
+                if !nodes_to_remove.is_empty() {
+                    replace_lost_neighbours(peer_clone.clone()).await;
                 }
-                // drop the guard on connection times.
-                drop(connection_times_guard);
-                sleep(Duration::from_secs(14)).await; // Pause for 14 second
+                sleep(sweep_interval).await;
             }
         });
     }
     // this is the main logic for listening and replying to requets.
     loop {
         // accept an incoming connection
-        let (mut stream, _) = listener.accept().await.unwrap();
-        // create a shared reference for connetion_times and peer.
+        let (stream, socket_addr) = listener.accept().await.unwrap();
+        // create a shared reference for peer.
         let peer_clone = peer.clone();
-        let connection_times_clone = connection_times.clone();
-        // spawn a thread for handling the incoming conneciton.
+        let transport = transport.clone();
+        // spawn a thread for handling the incoming conneciton, it may carry many messages.
         tokio::spawn(async move {
-            // reading and parsing the message from the buffer.
-            let mut buffer = [0; 1024];
-            let n = stream.read(&mut buffer).await.unwrap();
-            let message = String::from_utf8_lossy(&buffer[..n]).to_string();
-            let mut split_message: Vec<&str> = message.split('|').collect();
-            split_message.iter_mut().for_each(|s| *s = s.trim());
-            
-
-            // Acquire the lockfor message handling
-            let mut peer_guard = peer_clone.lock().await;
-            let mut connection_times_guard = connection_times_clone.lock().await;
-            // println!("Received message for peer {}: {}", peer_guard.local_addr, message);
-
-            // Response logic for Liveness Reply
-            if split_message[0] == LIVENESS_REQUEST{
-                let response = format!("{}|{}|{}|{}",
-                    LIVENESS_REPLY, split_message[1], split_message[2], peer_guard.local_addr);
-                send_liveness_reply(&split_message[2].to_string(), response).await;
-                // println!("Received Liveness Req\n");
-            }
-            // Response logic for Liveness Request
-            else if split_message[0] == LIVENESS_REPLY {
-                // update the connetion time for the node that replied.
-                let cur_timestamp: i32 = parse_and_convert_to_ms(peer_guard.elapsed_time().as_str());
-                let sender_ip = split_message[3].to_string();
-                *connection_times_guard.get_mut(&sender_ip).unwrap() = cur_timestamp;
-                // println!("Received Liveness Reply\n");
-            }
-            // Response logic for gossip message
-            else if split_message.len() == 3 {
-                let gossip_message = split_message[2];
-                // Checks whether the message is duplicate or not.
-                if peer_guard.message_list.contains(gossip_message) {
-                    // println!("Duplicate Message\n");
-                }else {
-                    println!("Peer@{}: Received new message: {} from {} at timestamp: {}",
-                        peer_guard.local_addr,gossip_message, split_message[1], split_message[0]);
-                    peer_guard.message_list.insert(gossip_message.to_string());
-                    let timestamp = peer_guard.elapsed_time();
-                    let formatted_msg = format!("{}|{}|{}", timestamp, peer_guard.local_addr, gossip_message);
-                    // broadcast the message to all the connected peers.
-                    broadcast_message(&peer_guard.connected_nodes, formatted_msg.to_string()).await;
-                }
-            }
-            // NO response when message is of incorrect format
-            else{
-                println!("Received Message of incorrect format\n");
-            }
-            // added due to too much fast leading to refused connection. can be fixed by raising the ulimit in terminal
-            // sleep(Duration::from_secs(1)).await; 
+            let mut conn = match transport.accept(stream).await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    println!("Peer@{}: Handshake with {} failed: {}", peer_clone.lock().await.local_addr, socket_addr, err);
+                    return;
+                }
+            };
+            // the sender of every message is checked against the connection it arrived on.
+            let mut identity = ConnectionIdentity::new(&conn);
+            loop {
+                // reading and decoding a complete message from the stream.
+                match conn.recv().await {
+                    Ok(message) => {
+                        // nodes checking a connection this peer opened are answered without taking the lock.
+                        if let Some(reply) = transport.dial_check_reply(&message) {
+                            let _ = conn.send(&reply).await;
+                            continue;
+                        }
+                        if let Err(reason) = identity.verify(&message, &transport).await {
+                            println!("Peer@{}: Rejected message from {}: {}",
+                                peer_clone.lock().await.local_addr, socket_addr, reason);
+                            return;
+                        }
+                        handle_message(&peer_clone, &mut conn, message).await;
+                    }
+                    // the other end closed its connection.
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => return,
+                    Err(err) => {
+                        println!("Received Message of incorrect format: {}\n", err);
+                        return;
+                    }
+                }
+            }
         });
     }
 }
 
+// Responds to a single message received on a connection.
+async fn handle_message(peer: &Arc<Mutex<Peer>>, conn: &mut Connection, message: Message) {
+    // Acquire the lockfor message handling
+    let mut peer_guard = peer.lock().await;
+    // println!("Received message for peer {}: {:?}", peer_guard.local_addr, message);
+    // membership updates piggybacked by the sender are applied whatever the message.
+    let updates = message.membership_updates().to_vec();
+    peer_guard.apply_membership(updates);
+
+    match message {
+        // Response logic for Liveness Reply
+        Message::LivenessRequest { timestamp, addr, .. } => {
+            let response = Message::LivenessReply {
+                timestamp,
+                requester: addr.clone(),
+                addr: peer_guard.local_addr.clone(),
+                updates: Vec::new(),
+            };
+            // seeds don't keep a membership view, updates are only piggybacked for peers.
+            let response = if peer_guard.seed_nodes.contains(&addr) {
+                response
+            } else {
+                peer_guard.piggyback(response)
+            };
+            peer_guard.pool.send(&addr, response);
+            // println!("Received Liveness Req\n");
+        }
+        // Response logic for Liveness Request
+        Message::LivenessReply { addr: sender_ip, .. } => {
+            // record the reply for the node that replied, if it is an agreed link.
+            peer_guard.failure_detector.heartbeat(&sender_ip, Instant::now());
+            // pass the reply on to the neighbours that asked for this node to be probed.
+            if let Some((_, requesters)) = peer_guard.indirect_probes.remove(&sender_ip) {
+                let ack = Message::IndirectAck { sender: peer_guard.local_addr.clone(), target: sender_ip };
+                for requester in requesters {
+                    peer_guard.pool.send(&requester, ack.clone());
+                }
+            }
+            // println!("Received Liveness Reply\n");
+        }
+        // Response logic for a neighbour handshake
+        Message::Connect { addr, .. } => {
+            let local_addr = peer_guard.local_addr.clone();
+            let response = if peer_guard.connected_nodes.contains(&addr) {
+                Message::Accept { addr: local_addr }
+            } else if peer_guard.connected_nodes.len() >= MAX_DEGREE {
+                let reason = format!("at maximum degree {}", MAX_DEGREE);
+                Message::Reject { addr: local_addr, reason }
+            } else {
+                peer_guard.add_neighbour(addr.clone());
+                println!("Peer@{}: Accepted link from {}", local_addr, addr);
+                tokio::spawn(check_liveness(peer.clone(), addr));
+                Message::Accept { addr: local_addr }
+            };
+            let _ = conn.send(&response).await;
+        }
+        // Response logic for gossip message
+        Message::Gossip { hlc, ref origin, ref origin_id, body: ref gossip_message, ref sender, hops, .. } => {
+            // Checks whether the message is duplicate or not, by its ID rather than its text.
+            let message_id = message.gossip_id().unwrap();
+            if peer_guard.seen_messages.contains(&message_id) {
+                // println!("Duplicate Message\n");
+                peer_guard.duplicates_received += 1;
+                // a link delivering duplicates isn't needed in the tree, it only carries announcements from now on.
+                let plumtree = peer_guard.config.dissemination == DisseminationStrategy::Plumtree;
+                if plumtree && peer_guard.connected_nodes.contains(sender) && peer_guard.tree.prune(sender) {
+                    let prune = Message::Prune { sender: peer_guard.local_addr.clone() };
+                    peer_guard.pool.send(sender, prune);
+                }
+                return;
+            }
+            // only messages really signed by their origin are accepted and forwarded.
+            if let Err(reason) = keys::verify_gossip(&message) {
+                println!("Peer@{}: Dropped gossip forwarded by {}: {}", peer_guard.local_addr, sender, reason);
+                return;
+            }
+            if let Err(reason) = peer_guard.check_origin_id(origin, origin_id, sender, conn.remote_id()) {
+                println!("Peer@{}: Dropped gossip forwarded by {}: {}", peer_guard.local_addr, sender, reason);
+                return;
+            }
+            // the receipt is stamped after the origin's stamp, so deliveries of every peer can be ordered together.
+            let delivered_at = match peer_guard.clock.observe(hlc) {
+                Ok(delivered_at) => delivered_at,
+                Err(reason) => {
+                    println!("Peer@{}: Dropped gossip forwarded by {}: {}", peer_guard.local_addr, sender, reason);
+                    return;
+                }
+            };
+            let delivery = Delivery { id: message_id, origin: origin.clone(), hops, hlc, delivered_at };
+            println!("Peer@{}: Received new message #{}: {} from {} via {} at hlc {} after {} hops, delivered at hlc {}",
+                peer_guard.local_addr, delivery.id.seq, gossip_message, delivery.origin, sender, hlc, delivery.hops, delivered_at);
+            peer_guard.seen_messages.insert(message.clone());
+            peer_guard.deliveries.record(delivery);
+            // the first copy of a message marks its link as part of the tree.
+            peer_guard.tree.graft(sender);
+            // the signed fields are forwarded unchanged, a message out of forwards is only delivered.
+            if let Some(formatted_msg) = message.forwarded_by(&peer_guard.local_addr) {
+                // push the message to the neighbours picked by the dissemination strategy.
+                peer_guard.disseminate(formatted_msg, Some(sender));
+            }
+        }
+        // A neighbour listed the messages it saw recently, exchange the ones either side is missing.
+        Message::Digest { sender, mut ids } => {
+            // only neighbours are answered, so nobody else can make this peer resend its messages.
+            if !peer_guard.connected_nodes.contains(&sender) {
+                println!("Peer@{}: Ignored digest from {} which is not a neighbour", peer_guard.local_addr, sender);
+                return;
+            }
+            let local_addr = peer_guard.local_addr.clone();
+            let digest_size = peer_guard.config.anti_entropy_digest_size;
+            // a digest shorter than a page lists everything the neighbour remembers, so it lacks the rest.
+            // Longer ones are only one page, the neighbour pulls what it lacks when this peer sends its own.
+            let complete = ids.len() < digest_size;
+            ids.truncate(digest_size);
+            // push the newest remembered messages the neighbour didn't list, a few per round.
+            let listed: HashSet<&MessageId> = ids.iter().collect();
+            let missing_there: Vec<MessageId> = if complete {
+                peer_guard.seen_messages.ids()
+                    .into_iter()
+                    .filter(|id| !listed.contains(id))
+                    .take(peer_guard.config.anti_entropy_push_limit)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            for id in &missing_there {
+                if let Some(message) = peer_guard.seen_messages.get(id) {
+                    let resent = message.resent_by(&local_addr);
+                    peer_guard.gossip_sent += 1;
+                    peer_guard.pool.send(&sender, resent);
+                }
+            }
+            // pull the listed messages this peer never saw.
+            let mut missing_here = Vec::new();
+            for id in ids {
+                if !peer_guard.seen_messages.contains(&id) {
+                    missing_here.push(id);
+                }
+            }
+            if !missing_there.is_empty() || !missing_here.is_empty() {
+                println!("Peer@{}: Anti-entropy with {}: pushed {}, pulling {}",
+                    local_addr, sender, missing_there.len(), missing_here.len());
+            }
+            if !missing_here.is_empty() {
+                peer_guard.pool.send(&sender, Message::Pull { sender: local_addr, ids: missing_here });
+            }
+        }
+        // A neighbour asked for messages it missed, send the ones still remembered.
+        Message::Pull { sender, ids } => {
+            // a pull answers one of this peer's digests, so it comes from a neighbour and lists at most a page.
+            if !peer_guard.connected_nodes.contains(&sender) {
+                println!("Peer@{}: Ignored pull from {} which is not a neighbour", peer_guard.local_addr, sender);
+                return;
+            }
+            let local_addr = peer_guard.local_addr.clone();
+            let digest_size = peer_guard.config.anti_entropy_digest_size;
+            for id in ids.into_iter().take(digest_size) {
+                if let Some(message) = peer_guard.seen_messages.get(&id) {
+                    let resent = message.resent_by(&local_addr);
+                    peer_guard.gossip_sent += 1;
+                    peer_guard.pool.send(&sender, resent);
+                }
+            }
+        }
+        // A neighbour announced messages on a lazy link, graft it if they don't arrive along the tree.
+        Message::IHave { sender, ids } => {
+            let mut missing = Vec::new();
+            for id in ids {
+                if !peer_guard.seen_messages.contains(&id) && peer_guard.tree.announce(id.clone()) {
+                    missing.push(id);
+                }
+            }
+            if !missing.is_empty() {
+                tokio::spawn(graft_missing(peer.clone(), sender, missing));
+            }
+        }
+        // A neighbour is missing messages, make its link eager again and send them.
+        Message::Graft { sender, ids } => {
+            peer_guard.tree.graft(&sender);
+            let local_addr = peer_guard.local_addr.clone();
+            for id in ids {
+                if let Some(message) = peer_guard.seen_messages.get(&id) {
+                    // the grafting peer keeps pushing it down its own part of the tree.
+                    let copy = message.forwarded_by(&local_addr)
+                        .unwrap_or_else(|| message.resent_by(&local_addr));
+                    peer_guard.gossip_sent += 1;
+                    peer_guard.pool.send(&sender, copy);
+                }
+            }
+        }
+        // A neighbour receives our messages along another path, only announce them from now on.
+        Message::Prune { sender } => {
+            peer_guard.tree.prune(&sender);
+        }
+        // A neighbour can't reach a node directly, probe it on its behalf.
+        Message::PingReq { sender, target } => {
+            if !peer_guard.accepts_ping_req(&sender, &target) {
+                println!("Peer@{}: Ignored probe request from {} for {}", peer_guard.local_addr, sender, target);
+                return;
+            }
+            // probes the target never answered are dropped after the suspicion timeout.
+            let suspicion_timeout = peer_guard.config.suspicion_timeout;
+            peer_guard.indirect_probes.retain(|_, (since, _)| since.elapsed() < suspicion_timeout);
+            peer_guard.indirect_probes.entry(target.clone())
+                .or_insert_with(|| (Instant::now(), HashSet::new()))
+                .1.insert(sender);
+            let request = Message::LivenessRequest {
+                timestamp: now_ms(),
+                addr: peer_guard.local_addr.clone(),
+                updates: Vec::new(),
+            };
+            let request = peer_guard.piggyback(request);
+            peer_guard.pool.send(&target, request);
+        }
+        // Another neighbour reached a suspected node, it is alive even if its link to this peer is lossy.
+        Message::IndirectAck { sender, target } => {
+            // only a neighbour asked to probe a node that is still suspected is believed.
+            if peer_guard.indirect_ack(&sender, &target, Instant::now()) {
+                println!("Peer@{}: Node {} answered an indirect probe through {}", peer_guard.local_addr, target, sender);
+            }
+        }
+        // A neighbour is leaving, stop tracking it at once.
+        Message::Leave { addr, .. } => {
+            peer_guard.record_member(&addr, MemberState::Left);
+            peer_guard.tree.forget(&addr);
+            peer_guard.failure_detector.forget(&addr);
+            peer_guard.pool.close(&addr);
+            if peer_guard.connected_nodes.remove(&addr) {
+                println!("Peer@{}: Node {} left the network.", peer_guard.local_addr, addr);
+                // find a new neighbour in its place.
+                tokio::spawn(replace_lost_neighbours(peer.clone()));
+            }
+            // the leaving peer waits for this before it stops listening.
+            let _ = conn.send(&Message::Response { text: format!("{} left", addr) }).await;
+        }
+        // NO response when message is of incorrect format
+        _ => {
+            println!("Received Message of incorrect format\n");
+        }
+    }
+}
+
 
 // Code for idle listener to simulate a dead node
 // Only Accepts messages and doesn't do anything.
@@ -203,14 +520,359 @@ pub async fn idle_listener(peer: Arc<Mutex<Peer>>){
     let listener = TcpListener::bind(&peer.lock().await.local_addr).await.unwrap();
     let peer_guard = peer.lock().await;
     println!("Peer {}: Idle Listening on {}", peer_guard.peer_no, peer_guard.local_addr);
+    let peer_guard_addr = peer_guard.local_addr.clone();
+    let transport = peer_guard.transport.clone();
     drop(peer_guard);
     loop {
-        let (mut stream, _) = listener.accept().await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let local_addr = peer_guard_addr.clone();
+        let transport = transport.clone();
         tokio::spawn(async move {
-            let mut buffer = [0; 1024];
-            let n = stream.read(&mut buffer).await.unwrap();
-            let _message = String::from_utf8_lossy(&buffer[..n]).to_string();
+            let Ok(mut conn) = transport.accept(stream).await else {
+                return;
+            };
+            // Links are still accepted so the node looks healthy until it is checked.
+            while let Ok(message) = conn.recv().await {
+                if let Some(reply) = transport.dial_check_reply(&message) {
+                    let _ = conn.send(&reply).await;
+                } else if let Message::Connect { .. } = message {
+                    let _ = conn.send(&Message::Accept { addr: local_addr.clone() }).await;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gossip_protocol::NodeKey;
+    use crate::config::PeerConfig;
+    use crate::tokio_peer::key_path;
 
+    #[tokio::test]
+    async fn seeds_are_queried_without_holding_the_lock() {
+        // a seed that accepts connections but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let seed_addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
         });
+        let dir = tempfile::tempdir().unwrap();
+        let key = NodeKey::load_or_generate(&dir.path().join("peer.key")).unwrap();
+        let peer = Arc::new(Mutex::new(
+            Peer::new(1, "127.0.0.1:9090".to_string(), vec![seed_addr], PeerConfig::default(), key)));
+
+        let query = tokio::spawn({
+            let peer = peer.clone();
+            async move { find_neighbour_candidates(&peer).await }
+        });
+        sleep(Duration::from_millis(200)).await;
+        assert!(peer.try_lock().is_ok(), "the lock is held while waiting for the seed");
+        // the silent seed is given up on instead of stalling the peer.
+        let candidates = timeout(Duration::from_secs(10), query).await.unwrap().unwrap();
+        assert!(candidates.is_empty());
+    }
+
+    // Starts `count` linked peers on loopback whose seeds are gone, they only know each other
+    // through their membership views. Liveness is checked every 200 ms so failures show quickly.
+    async fn start_seedless_peers(dir: &std::path::Path, count: usize) -> Vec<Arc<Mutex<Peer>>> {
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let gone_seed = closed.local_addr().unwrap().to_string();
+        drop(closed);
+        let config = PeerConfig {
+            trusted_keys: vec![dir.to_path_buf()],
+            liveness_interval: Duration::from_millis(200),
+            liveness_sweep: Duration::from_millis(100),
+            liveness_timeout: Duration::from_secs(1),
+            suspicion_timeout: Duration::from_millis(500),
+            ..PeerConfig::default()
+        };
+        let mut addrs = Vec::new();
+        for _ in 0..count {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap().to_string());
+        }
+        let mut peers = Vec::new();
+        for (index, addr) in addrs.iter().enumerate() {
+            let key = NodeKey::load_or_generate(&key_path(dir, addr)).unwrap();
+            let peer = Peer::new(index as i32 + 1, addr.clone(), vec![gone_seed.clone()], config.clone(), key);
+            let peer = Arc::new(Mutex::new(peer));
+            for other in addrs.iter().filter(|other| *other != addr) {
+                peer.lock().await.membership.discover(other);
+            }
+            tokio::spawn(spawn_listener(peer.clone()));
+            peers.push(peer);
+        }
+        sleep(Duration::from_millis(100)).await;
+        for peer in &peers {
+            let candidates = find_neighbour_candidates(peer).await;
+            connect_neighbours(peer.clone(), candidates).await;
+        }
+        peers
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn survivors_repair_their_degree_without_the_seeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_seedless_peers(dir.path(), 8).await;
+        // the victim hangs, like the idle peer the binary simulates, everything it does waits for its lock.
+        let victim = peers[0].lock().await.local_addr.clone();
+        let _hung = peers[0].lock().await;
+        let survivors = &peers[1..];
+
+        let repaired = async {
+            loop {
+                let mut repaired = true;
+                for peer in survivors {
+                    let peer_guard = peer.lock().await;
+                    repaired &= !peer_guard.connected_nodes.contains(&victim)
+                        && peer_guard.connected_nodes.len() >= TARGET_DEGREE;
+                }
+                if repaired {
+                    return;
+                }
+                sleep(Duration::from_millis(100)).await;
+            }
+        };
+        timeout(Duration::from_secs(20), repaired).await.expect("survivors didn't repair their degree");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_peer_that_missed_messages_catches_up_from_a_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_seedless_peers(dir.path(), 2).await;
+        // messages the second peer never received, e.g. while it was away.
+        let origin = {
+            let mut peer_guard = peers[0].lock().await;
+            for itr in 0..3 {
+                peer_guard.originate_gossip(format!("missed #{}", itr));
+            }
+            peer_guard.local_addr.clone()
+        };
+        {
+            let mut peer_guard = peers[1].lock().await;
+            assert!(peer_guard.seen_messages.ids().is_empty());
+            let digest = Message::Digest { sender: peer_guard.local_addr.clone(), ids: peer_guard.seen_messages.ids() };
+            peer_guard.pool.send(&origin, digest);
+        }
+
+        let caught_up = async {
+            while peers[1].lock().await.deliveries.ordered().len() < 3 {
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(Duration::from_secs(10), caught_up).await.expect("the missed messages weren't sent back");
     }
-}
\ No newline at end of file
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_digest_is_answered_with_a_bounded_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_seedless_peers(dir.path(), 2).await;
+        let origin = {
+            let mut peer_guard = peers[0].lock().await;
+            peer_guard.config.anti_entropy_push_limit = 4;
+            for itr in 0..10 {
+                peer_guard.originate_gossip(format!("missed #{}", itr));
+            }
+            peer_guard.local_addr.clone()
+        };
+        {
+            let peer_guard = peers[1].lock().await;
+            let digest = Message::Digest { sender: peer_guard.local_addr.clone(), ids: Vec::new() };
+            peer_guard.pool.send(&origin, digest);
+        }
+        let pushed = async {
+            while peers[1].lock().await.deliveries.ordered().len() < 4 {
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(Duration::from_secs(10), pushed).await.expect("the missed messages weren't sent back");
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(peers[1].lock().await.deliveries.ordered().len(), 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn digests_from_other_nodes_than_neighbours_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_linked_peers(dir.path(), 3, &[(0, 1)], DisseminationStrategy::Flood).await;
+        let origin = {
+            let mut peer_guard = peers[0].lock().await;
+            peer_guard.originate_gossip("kept for neighbours".to_string());
+            peer_guard.local_addr.clone()
+        };
+        for peer in &peers[1..] {
+            let peer_guard = peer.lock().await;
+            let digest = Message::Digest { sender: peer_guard.local_addr.clone(), ids: Vec::new() };
+            peer_guard.pool.send(&origin, digest);
+        }
+        let pushed = async {
+            while peers[1].lock().await.deliveries.ordered().is_empty() {
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(Duration::from_secs(10), pushed).await.expect("the neighbour wasn't answered");
+        assert!(peers[2].lock().await.deliveries.ordered().is_empty());
+    }
+
+    #[test]
+    fn digests_page_through_every_remembered_id() {
+        let ids: Vec<MessageId> = (0..5).map(|seq| MessageId { origin_id: vec![1], seq }).collect();
+        let mut cursor = 0;
+        // a short list fits in one digest, which then lists everything.
+        assert_eq!(digest_page(&ids, &mut cursor, 8), ids);
+        let mut listed = Vec::new();
+        for _ in 0..3 {
+            let page = digest_page(&ids, &mut cursor, 2);
+            assert_eq!(page.len(), 2);
+            listed.extend(page);
+        }
+        // three pages of two went round the five IDs once and started over.
+        assert_eq!(listed[..5], ids[..]);
+        assert_eq!(listed[5], ids[0]);
+        assert!(digest_page(&[], &mut cursor, 2).is_empty());
+    }
+
+    // Starts peers on loopback linked along `links`, pairs of peer indexes, with the given strategy.
+    // Liveness isn't checked, the links stay as they are for the whole test.
+    async fn start_linked_peers(dir: &std::path::Path, count: usize, links: &[(usize, usize)],
+        dissemination: DisseminationStrategy) -> Vec<Arc<Mutex<Peer>>> {
+        let config = PeerConfig {
+            trusted_keys: vec![dir.to_path_buf()],
+            dissemination,
+            graft_timeout: Duration::from_millis(200),
+            ..PeerConfig::default()
+        };
+        let mut peers = Vec::new();
+        for index in 0..count {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap().to_string();
+            drop(listener);
+            let key = NodeKey::load_or_generate(&key_path(dir, &addr)).unwrap();
+            let peer = Arc::new(Mutex::new(Peer::new(index as i32 + 1, addr, Vec::new(), config.clone(), key)));
+            tokio::spawn(spawn_listener(peer.clone()));
+            peers.push(peer);
+        }
+        sleep(Duration::from_millis(100)).await;
+        for &(a, b) in links {
+            let addr_a = peers[a].lock().await.local_addr.clone();
+            let addr_b = peers[b].lock().await.local_addr.clone();
+            peers[a].lock().await.add_neighbour(addr_b);
+            peers[b].lock().await.add_neighbour(addr_a);
+        }
+        peers
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_leaving_peer_waits_until_its_neighbours_removed_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_linked_peers(dir.path(), 2, &[(0, 1)], DisseminationStrategy::Flood).await;
+        let leaving = peers[0].lock().await.local_addr.clone();
+        // the neighbour checks the LEAVE by connecting back before it acknowledges.
+        peers[0].lock().await.leave_network().await;
+        let neighbour = peers[1].lock().await;
+        assert!(!neighbour.connected_nodes.contains(&leaving));
+        assert!(!neighbour.membership.live_members().contains(&leaving));
+    }
+
+    // The peers at `origins` each originate one message, returns the duplicates received by all peers
+    // once every message of this and the previous rounds reached every peer and the last copies settled.
+    async fn broadcast_round(peers: &[Arc<Mutex<Peer>>], origins: &[usize], round: usize) -> u64 {
+        let mut before = 0;
+        for peer in peers {
+            before += peer.lock().await.duplicates_received;
+        }
+        for &origin in origins {
+            let mut peer_guard = peers[origin].lock().await;
+            let message = peer_guard.originate_gossip(format!("round {}", round));
+            peer_guard.disseminate(message, None);
+        }
+        // a peer never delivers its own messages, so this only adds up once each peer has every other one.
+        let expected = origins.len() * (round + 1) * (peers.len() - 1);
+        let delivered = async {
+            loop {
+                let mut deliveries = 0;
+                for peer in peers {
+                    deliveries += peer.lock().await.deliveries.ordered().len();
+                }
+                if deliveries == expected {
+                    return;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(Duration::from_secs(20), delivered).await.expect("not every message reached every peer");
+        sleep(Duration::from_millis(300)).await;
+        let mut after = 0;
+        for peer in peers {
+            after += peer.lock().await.duplicates_received;
+        }
+        after - before
+    }
+
+    // Counts the lazy links of every peer.
+    async fn lazy_links(peers: &[Arc<Mutex<Peer>>]) -> usize {
+        let mut lazy = 0;
+        for peer in peers {
+            let peer_guard = peer.lock().await;
+            lazy += peer_guard.tree.lazy_links(&peer_guard.connected_nodes, None).len();
+        }
+        lazy
+    }
+
+    // A ring with chords, every peer has 4 neighbours.
+    fn ring_with_chords(count: usize) -> Vec<(usize, usize)> {
+        (0..count)
+            .flat_map(|peer| [(peer, (peer + 1) % count), (peer, (peer + 3) % count)])
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn plumtree_receives_fewer_duplicates_than_flood() {
+        let count = 10;
+        let links = ring_with_chords(count);
+        let origins: Vec<usize> = (0..count).collect();
+        let rounds = 5;
+
+        let flood_dir = tempfile::tempdir().unwrap();
+        let flood = start_linked_peers(flood_dir.path(), count, &links, DisseminationStrategy::Flood).await;
+        let mut flood_rounds = Vec::new();
+        for round in 0..rounds {
+            flood_rounds.push(broadcast_round(&flood, &origins, round).await);
+        }
+        let plumtree_dir = tempfile::tempdir().unwrap();
+        let plumtree = start_linked_peers(plumtree_dir.path(), count, &links, DisseminationStrategy::Plumtree).await;
+        let mut plumtree_rounds = Vec::new();
+        for round in 0..rounds {
+            plumtree_rounds.push(broadcast_round(&plumtree, &origins, round).await);
+        }
+
+        println!("duplicates per round, flood: {:?}, plumtree: {:?}", flood_rounds, plumtree_rounds);
+        assert_eq!(lazy_links(&flood).await, 0);
+        assert!(lazy_links(&plumtree).await > 0, "no link was pruned");
+        assert!(plumtree_rounds.iter().sum::<u64>() < flood_rounds.iter().sum::<u64>());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn plumtree_prunes_the_links_a_steady_source_does_not_need() {
+        let count = 10;
+        let dir = tempfile::tempdir().unwrap();
+        let peers = start_linked_peers(dir.path(), count, &ring_with_chords(count), DisseminationStrategy::Plumtree).await;
+        let rounds = 5;
+        let mut duplicates = vec![broadcast_round(&peers, &[0], 0).await];
+        // the duplicates of the first message pruned links out of the tree.
+        let lazy = lazy_links(&peers).await;
+        for round in 1..rounds {
+            duplicates.push(broadcast_round(&peers, &[0], round).await);
+        }
+
+        println!("duplicates per round: {:?}, {} lazy links after the first round", duplicates, lazy);
+        assert!(lazy > 0, "no link was pruned");
+        // later messages follow the tree and far fewer duplicates arrive.
+        let (first, last) = (duplicates[0], duplicates[rounds - 1]);
+        assert!(last * 2 < first, "the last round got {} duplicates, the first {}", last, first);
+    }
+}
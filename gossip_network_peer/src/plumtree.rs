@@ -0,0 +1,60 @@
+//! Plumtree broadcast tree built on a peer's neighbour links
+
+// importing necessary packages
+use std::collections::HashSet;
+use gossip_protocol::MessageId;
+
+// Splits the neighbour links into eager links, which carry whole messages along a spanning tree,
+// and lazy links, which only carry IHAVE announcements used to repair the tree.
+// Every neighbour starts eager, links delivering duplicates are pruned to lazy.
+pub struct BroadcastTree {
+    lazy: HashSet<String>, // neighbours only sent announcements.
+    announced: HashSet<MessageId>, // missing messages waiting for their graft timer.
+}
+
+impl BroadcastTree {
+    pub fn new() -> Self {
+        BroadcastTree { lazy: HashSet::new(), announced: HashSet::new() }
+    }
+
+    // Returns the neighbours messages are pushed to, leaving out the one it came from.
+    pub fn eager_links(&self, neighbours: &HashSet<String>, except: Option<&str>) -> HashSet<String> {
+        neighbours.iter()
+            .filter(|node| !self.lazy.contains(*node) && Some(node.as_str()) != except)
+            .cloned()
+            .collect()
+    }
+
+    // Returns the neighbours messages are announced to, leaving out the one it came from.
+    pub fn lazy_links(&self, neighbours: &HashSet<String>, except: Option<&str>) -> Vec<String> {
+        neighbours.iter()
+            .filter(|node| self.lazy.contains(*node) && Some(node.as_str()) != except)
+            .cloned()
+            .collect()
+    }
+
+    // Makes a link eager, returns true if it was lazy.
+    pub fn graft(&mut self, node: &str) -> bool {
+        self.lazy.remove(node)
+    }
+
+    // Makes a link lazy, returns true if it was eager.
+    pub fn prune(&mut self, node: &str) -> bool {
+        self.lazy.insert(node.to_string())
+    }
+
+    // Forgets a neighbour that is no longer linked.
+    pub fn forget(&mut self, node: &str) {
+        self.lazy.remove(node);
+    }
+
+    // Records an announced message this peer is missing, returns false if a graft timer already runs for it.
+    pub fn announce(&mut self, id: MessageId) -> bool {
+        self.announced.insert(id)
+    }
+
+    // Ends the graft timer of an announced message.
+    pub fn settle(&mut self, id: &MessageId) {
+        self.announced.remove(id);
+    }
+}
@@ -1,15 +1,36 @@
-/// Contains the Struct and functions for Peer Node
+//! Contains the Struct and functions for Peer Node
 
 // importing necessary packages
-use std::collections::HashSet;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use chrono::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use gossip_protocol::{keys, now_ms, HybridClock, MemberState, MembershipUpdate, Message, NodeKey, Transport};
 
-// Constants for specific reply
-const JOIN_REQUEST_MESSAGE: &str = "JOIN_REQUEST";
-const GET_CONNECTED_NODES_REQUEST: &str = "GET_CONNECTED_NODES_REQUEST";
-const DEAD_NODE_MESSAGE: &str = "DEAD_NODE";
+// importing necessary files
+use crate::config::PeerConfig;
+use crate::connection_pool::ConnectionPool;
+use crate::dedup_cache::DedupCache;
+use crate::delivery_log::DeliveryLog;
+use crate::dissemination::DisseminationStrategy;
+use crate::failure_detector::FailureDetector;
+use crate::membership::MembershipView;
+use crate::plumtree::BroadcastTree;
+use crate::utils::select_k_nodes;
+
+// Number of peers requested from a seed per GET_CONNECTED_NODES_REQUEST.
+const PEER_LIST_PAGE_SIZE: u32 = 64;
+// How long a seed or neighbour has to accept a connection or answer, so an unreachable one doesn't stall the peer.
+const SEED_TIMEOUT: Duration = Duration::from_secs(5);
+// How long an origin's key file isn't read again after a lookup, so unknown origins don't cost a read per message.
+const KEY_LOOKUP_INTERVAL: Duration = Duration::from_secs(5);
+// Number of distinct peers each peer tries to keep as neighbours.
+pub const TARGET_DEGREE: usize = 4;
+// Number of neighbours above which a peer refuses new links.
+pub const MAX_DEGREE: usize = 8;
 
 // Peer Struct
 pub struct Peer {
@@ -17,36 +38,85 @@ pub struct Peer {
     pub local_addr: String, // stores peer address i.e IP:PORT
     pub seed_nodes: Vec<String>, // Stores the connected seed nodes.
     pub connected_nodes: HashSet<String>, // stores the connected distinct peer nodes
-    pub message_list: HashSet<String>, // stores the messages received
-    pub creation_time: DateTime<Utc>, // stores the local time when this peer was created.
+    pub seen_messages: DedupCache, // gossip messages received or sent recently.
+    pub next_seq: u64, // sequence number of the next gossip message this peer creates.
+    pub deliveries: DeliveryLog, // gossip messages delivered to this peer and their hop counts.
+    pub gossip_sent: u64, // gossip copies this peer sent, its share of the dissemination overhead.
+    pub duplicates_received: u64, // gossip copies this peer received after already seeing the message.
+    pub tree: BroadcastTree, // eager and lazy links used by the plumtree strategy.
+    pub failure_detector: Box<dyn FailureDetector>, // liveness replies of the connected nodes, tells which are dead.
+    pub suspects: HashMap<String, Instant>, // neighbours that stopped replying and since when, not yet confirmed dead.
+    pub indirect_probes: HashMap<String, (Instant, HashSet<String>)>, // nodes probed for others, when and for whom.
+    pub probes_requested: HashMap<String, HashSet<String>>, // suspects this peer asked others to probe, and whom.
+    pub incarnation: u64, // raised to refute suspicions about this peer.
+    pub membership: MembershipView, // state of the other peers, kept up to date without the seeds.
+    pub clock: HybridClock, // hybrid logical clock stamping created and delivered gossip.
+    pub declared_dead: HashMap<String, Instant>, // nodes this peer declared dead and when, not picked as neighbours for a while.
+    pub pool: ConnectionPool, // long-lived connections to neighbours and other nodes this peer writes to.
+    pub key: NodeKey, // signing key, its public half is this peer's node ID.
+    pub transport: Arc<Transport>, // opens and accepts this peer's connections.
+    pub registered_ids: HashMap<String, Vec<u8>>, // node ID registered in the trusted key directories for each origin address.
+    pub key_lookups: HashMap<String, Instant>, // origins whose key file was read recently and when.
+    pub config: PeerConfig, // tunable settings.
 }
 
 impl Peer {
-    pub fn new(peer_no: i32, local_addr:String, seed_nodes: Vec<String>) -> Self {
-        let creation_time = Utc::now();
-        Peer {
+    pub fn new(peer_no: i32, local_addr:String, seed_nodes: Vec<String>, config: PeerConfig, key: NodeKey) -> Self {
+        let transport = Arc::new(config.transport(&key));
+        let mut peer = Peer {
             peer_no,
             local_addr,
             seed_nodes,
             connected_nodes: HashSet::new(),
-            message_list: HashSet::new(),
-            creation_time,
-        }
+            seen_messages: DedupCache::new(config.dedup_retention, config.dedup_capacity),
+            // starting from the wall clock keeps IDs unique across restarts.
+            next_seq: now_ms(),
+            deliveries: DeliveryLog::new(config.delivery_log_capacity),
+            gossip_sent: 0,
+            duplicates_received: 0,
+            tree: BroadcastTree::new(),
+            failure_detector: config.failure_detector(),
+            suspects: HashMap::new(),
+            indirect_probes: HashMap::new(),
+            probes_requested: HashMap::new(),
+            incarnation: 0,
+            membership: MembershipView::new(config.membership_retransmits, config.membership_piggyback),
+            clock: HybridClock::new(config.hlc_max_drift),
+            declared_dead: HashMap::new(),
+            pool: ConnectionPool::new(transport.clone(), config.pool_idle_timeout),
+            key,
+            transport,
+            registered_ids: HashMap::new(),
+            key_lookups: HashMap::new(),
+            config,
+        };
+        // the other peers learn about this one from its own messages.
+        let joined = MembershipUpdate { addr: peer.local_addr.clone(), state: MemberState::Alive, incarnation: 0 };
+        peer.membership.announce(joined);
+        peer
     }
-    // Sends a Request to the seed nodes to join.
-    pub async fn join_seed_nodes(&mut self,) {
+    // Sends a Request to the seed nodes to join, presenting the join token if one is configured.
+    pub async fn join_seed_nodes(&mut self) {
         for seed_node in &self.seed_nodes {
-            match TcpStream::connect(seed_node).await {
-                Ok(mut stream) => {
-                    let response = format!("{}|{}|{}", JOIN_REQUEST_MESSAGE, &self.local_addr, self.elapsed_time());
-                    stream.write_all(response.as_bytes()).await.unwrap();
+            match self.transport.connect(seed_node).await {
+                Ok(mut conn) => {
+                    let request = Message::JoinRequest {
+                        addr: self.local_addr.clone(),
+                        timestamp: now_ms(),
+                        token: self.config.join_token.clone(),
+                    };
+                    conn.send(&request).await.unwrap();
                     // println!("Sent JOIN_REQUEST to seed: {:?}", seed_node);
 
                     // Wait for the response
-                    let mut buffer = [0; 1024];
-                    if let Ok(n) = stream.read(&mut buffer).await {
-                        let _response = String::from_utf8_lossy(&buffer[..n]);
-                        // println!("Received response from seed: {:?}", response);
+                    match conn.recv().await {
+                        Ok(Message::Reject { addr, reason }) => {
+                            println!("Peer@{}: Join refused by seed {}: {}", self.local_addr, addr, reason);
+                        }
+                        Ok(_response) => {
+                            // println!("Received response from seed: {:?}", response);
+                        }
+                        Err(_) => {}
                     }
                 }
                 Err(e) => {
@@ -55,80 +125,500 @@ impl Peer {
             }
         }
     }
-    // Internal function the parse the message.
-    fn _extract_nodes(input_string: &str) -> Vec<String> {
-        let nodes_str = input_string.trim().replace("Connected Nodes: [", "");
-        let nodes_str = &nodes_str.replace("]", "");
-        let nodes_str = &nodes_str.replace("\\", "");
-        let nodes_str = &nodes_str.replace("\"", "");
+    // Returns the peers listed by the seeds or known to the membership view that could become new neighbours.
+    // Once the seeds are gone, the membership view alone provides them. A node this peer declared dead
+    // is skipped for `dead_node_quarantine`, after that a seed still listing it means it is back.
+    pub fn neighbour_candidates(&mut self, listed: HashSet<String>) -> Vec<String> {
+        let now = Instant::now();
+        let quarantine = self.config.dead_node_quarantine;
+        self.declared_dead.retain(|_, declared_at| now.duration_since(*declared_at) < quarantine);
+        for node in &listed {
+            self.membership.discover(node);
+        }
+        let mut candidates: HashSet<String> = self.membership.live_members().into_iter().collect();
+        candidates.extend(listed);
+        candidates.into_iter()
+            .filter(|node| !self.connected_nodes.contains(node) && !self.declared_dead.contains_key(node))
+            .collect()
+    }
 
-        let nodes_str: Vec<String> = nodes_str.split(", ").map(|s| s.to_string()).collect();
-    
-        // Gather parsed nodes into a Vec and return it.
-        return nodes_str;
+    // Records an agreed link with a neighbour, returns false if it was already known.
+    pub fn add_neighbour(&mut self, node: String) -> bool {
+        if !self.connected_nodes.insert(node.clone()) {
+            return false;
+        }
+        // new neighbours are watched as if they had just replied.
+        self.failure_detector.watch(&node, Instant::now());
+        true
     }
-    
-    // Queries the connected nodes form each seed.
-    pub async fn query_connected_nodes(&mut self) {
-        for seed_node in &self.seed_nodes {
-            if let Ok(mut stream) = TcpStream::connect(seed_node).await {
+
+    // Creates and signs a new gossip message, remembering it so it isn't accepted back.
+    pub fn originate_gossip(&mut self, body: String) -> Message {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let message = self.key.sign_gossip(self.clock.now(), self.local_addr.clone(), seq, body, self.config.gossip_ttl);
+        self.seen_messages.insert(message.clone());
+        message
+    }
+
+    // Sends a gossip message to the neighbours picked by the dissemination strategy.
+    // `from` is the neighbour a forwarded message came from, None for messages created by this peer.
+    pub fn disseminate(&mut self, message: Message, from: Option<&str>) {
+        let neighbours = if self.config.dissemination == DisseminationStrategy::Plumtree {
+            // whole messages follow the tree, the other links only get an announcement.
+            if let Some(id) = message.gossip_id() {
+                let announcement = Message::IHave { sender: self.local_addr.clone(), ids: vec![id] };
+                for target in self.tree.lazy_links(&self.connected_nodes, from) {
+                    self.pool.send(&target, announcement.clone());
+                }
+            }
+            self.tree.eager_links(&self.connected_nodes, from)
+        } else {
+            self.connected_nodes.clone()
+        };
+        let targets = self.config.dissemination.targets(&neighbours, from.is_none());
+        let message = self.piggyback(message);
+        self.gossip_sent += targets.len() as u64;
+        for target in targets {
+            self.pool.send(&target, message.clone());
+        }
+    }
+
+    // Moves the neighbours the failure detector found silent to the suspect state and asks other
+    // neighbours to probe them. Returns the suspects still silent after the suspicion timeout, confirmed dead.
+    pub fn update_suspects(&mut self, failed: Vec<String>, now: Instant) -> Vec<String> {
+        // suspects that replied again, directly or through another neighbour, are cleared.
+        let local_addr = &self.local_addr;
+        let connected_nodes = &self.connected_nodes;
+        self.suspects.retain(|node, _| {
+            let silent = failed.contains(node);
+            if !silent && connected_nodes.contains(node) {
+                println!("Peer@{}: Node {} is no longer suspected", local_addr, node);
+            }
+            silent
+        });
+        let mut confirmed = Vec::new();
+        for node in failed {
+            match self.suspects.get(&node) {
+                Some(since) if now.duration_since(*since) >= self.config.suspicion_timeout => confirmed.push(node),
+                Some(_) => {}
+                None => {
+                    println!("Peer@{}: Suspecting {}, probing it through other neighbours", self.local_addr, node);
+                    self.record_member(&node, MemberState::Suspect);
+                    self.suspects.insert(node.clone(), now);
+                    self.probe_indirectly(&node);
+                }
+            }
+        }
+        for node in &confirmed {
+            self.suspects.remove(node);
+            self.record_member(node, MemberState::Dead);
+        }
+        // probes are only outstanding while their target is suspected.
+        let suspects = &self.suspects;
+        self.probes_requested.retain(|node, _| suspects.contains_key(node));
+        confirmed
+    }
+
+    // Asks up to `indirect_probes` other neighbours to probe a suspected neighbour.
+    fn probe_indirectly(&mut self, target: &str) {
+        let helpers: Vec<String> = self.connected_nodes.iter()
+            .filter(|node| *node != target)
+            .cloned()
+            .collect();
+        let k = self.config.indirect_probes.min(helpers.len());
+        let request = Message::PingReq { sender: self.local_addr.clone(), target: target.to_string() };
+        let helpers = select_k_nodes(helpers, k);
+        for helper in &helpers {
+            self.pool.send(helper, request.clone());
+        }
+        self.probes_requested.insert(target.to_string(), helpers.into_iter().collect());
+    }
+
+    // Records a neighbour's answer to an indirect probe, returns false if it wasn't asked for one.
+    // Only a helper that was sent a PING_REQ for a still suspected target may vouch for it,
+    // or any node could keep a dead neighbour alive.
+    pub fn indirect_ack(&mut self, helper: &str, target: &str, now: Instant) -> bool {
+        let asked = self.suspects.contains_key(target)
+            && self.probes_requested.get(target).is_some_and(|helpers| helpers.contains(helper));
+        if asked {
+            self.failure_detector.heartbeat(target, now);
+        }
+        asked
+    }
+
+    // Checks a PING_REQ, only neighbours may ask and only about peers in the membership view,
+    // so this peer can't be used to send liveness requests to any address.
+    pub fn accepts_ping_req(&self, sender: &str, target: &str) -> bool {
+        self.connected_nodes.contains(sender) && target != self.local_addr && self.membership.is_live(target)
+    }
+
+    // Records a state change this peer observed about another peer, to be passed on to the others.
+    pub fn record_member(&mut self, addr: &str, state: MemberState) {
+        let incarnation = self.membership.incarnation(addr);
+        self.membership.apply(MembershipUpdate { addr: addr.to_string(), state, incarnation });
+    }
+
+    // Applies the membership updates piggybacked on a received message.
+    pub fn apply_membership(&mut self, updates: Vec<MembershipUpdate>) {
+        for update in updates {
+            if update.addr == self.local_addr {
+                // a running peer refutes any claim it is gone with a newer incarnation.
+                if update.state != MemberState::Alive && update.incarnation >= self.incarnation {
+                    // a claim at the last incarnation can't be outbid, it is bogus and ignored.
+                    let Some(incarnation) = update.incarnation.checked_add(1) else {
+                        println!("Peer@{}: Ignored {:?} at incarnation {}, it can't be refuted",
+                            self.local_addr, update.state, update.incarnation);
+                        continue;
+                    };
+                    self.incarnation = incarnation;
+                    println!("Peer@{}: Refuting {:?} with incarnation {}", self.local_addr, update.state, self.incarnation);
+                    let alive = MembershipUpdate { addr: self.local_addr.clone(), state: MemberState::Alive, incarnation: self.incarnation };
+                    self.membership.announce(alive);
+                }
+                continue;
+            }
+            let (addr, state, incarnation) = (update.addr.clone(), update.state, update.incarnation);
+            if !self.membership.apply(update) {
+                continue;
+            }
+            if state != MemberState::Alive {
+                println!("Peer@{}: Membership update: {} is {:?} at incarnation {}", self.local_addr, addr, state, incarnation);
+            } else if self.declared_dead.remove(&addr).is_some() {
+                // a newer incarnation refuted the death, the node may be picked as a neighbour again.
+                println!("Peer@{}: Node {} is alive again at incarnation {}", self.local_addr, addr, incarnation);
+            }
+        }
+    }
+
+    // Returns the message carrying the next membership updates to pass on.
+    pub fn piggyback(&mut self, message: Message) -> Message {
+        message.with_updates(self.membership.piggyback())
+    }
+
+    // Checks the node ID signing gossip for an origin, returns the reason if it isn't allowed to.
+    // Every origin address is bound to the node ID registered for it in the trusted key directories,
+    // so a trusted node can't sign gossip in another's name. `remote_id` is the node ID that
+    // authenticated the connection the message arrived on, if any, a message coming straight
+    // from its origin must be signed by it.
+    pub fn check_origin_id(&mut self, origin: &str, origin_id: &[u8], sender: &str, remote_id: Option<&[u8]>) -> Result<(), String> {
+        // nobody else may sign gossip in this peer's name.
+        if origin == self.local_addr {
+            if origin_id != self.key.node_id() {
+                return Err(format!("{} is this peer's address but signed with another node ID", origin));
+            }
+            return Ok(());
+        }
+        if let Some(remote_id) = remote_id {
+            if sender == origin && remote_id != origin_id {
+                return Err(format!("{} signed with another node ID than its connection", origin));
+            }
+        }
+        // the origin names a key file, so it must be a plain address.
+        if origin.parse::<SocketAddr>().is_err() {
+            return Err(format!("{} is not a valid origin address", origin));
+        }
+        // the key file is read again for an unknown or changed ID, the peer may have been started since.
+        if self.registered_ids.get(origin).map(Vec::as_slice) != Some(origin_id) {
+            self.lookup_registered_id(origin, Instant::now());
+        }
+        match self.registered_ids.get(origin) {
+            Some(registered) if registered == origin_id => Ok(()),
+            Some(_) => Err(format!("{} signed with node ID {} but another one is registered for it",
+                origin, keys::node_id_hex(origin_id))),
+            None => Err(format!("{} has no node ID in the trusted key directories", origin)),
+        }
+    }
+
+    // Reads the node ID registered for an origin from the first trusted directory holding its key file.
+    // An origin is looked up at most once per `KEY_LOOKUP_INTERVAL`, messages in between use what was read.
+    fn lookup_registered_id(&mut self, origin: &str, now: Instant) {
+        self.key_lookups.retain(|_, read_at| now.duration_since(*read_at) < KEY_LOOKUP_INTERVAL);
+        if self.key_lookups.contains_key(origin) {
+            return;
+        }
+        self.key_lookups.insert(origin.to_string(), now);
+        for dir in &self.config.trusted_keys {
+            let path = key_path(dir, origin).with_extension("pub");
+            match keys::load_node_id(&path) {
+                Ok(node_id) => {
+                    self.registered_ids.insert(origin.to_string(), node_id);
+                    return;
+                }
+                // a directory without this peer's key simply doesn't register it.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => eprintln!("Peer@{}: Failed to read {}: {}", self.local_addr, path.display(), err),
+            }
+        }
+        // a removed key file revokes the origin.
+        self.registered_ids.remove(origin);
+    }
+
+    // Queries the connected nodes form each seed, one page at a time over a single connection.
+    // It takes what it needs from the peer rather than the peer, so the peer's lock isn't held meanwhile.
+    pub async fn query_connected_nodes(transport: &Transport, local_addr: &str, seed_nodes: &[String]) -> HashSet<String> {
+        let mut peer_list = HashSet::new();
+        for seed_node in seed_nodes {
+            let mut conn = match timeout(SEED_TIMEOUT, transport.connect(seed_node)).await {
+                Ok(Ok(conn)) => conn,
+                _ => {
+                    eprintln!("Peer@{}: Failed to connect to {:?}", local_addr, seed_node);
+                    continue;
+                }
+            };
+            let mut offset: u32 = 0;
+            loop {
                 // Send GET_CONNECTED_NODES_REQUEST message to seed
-                let response = format!("{}|{}|{}", GET_CONNECTED_NODES_REQUEST, &self.local_addr, self.elapsed_time());
-                stream.write_all(response.as_bytes()).await.unwrap();
+                let request = Message::GetConnectedNodes {
+                    addr: local_addr.to_string(),
+                    timestamp: now_ms(),
+                    offset,
+                    limit: Some(PEER_LIST_PAGE_SIZE),
+                };
+                if conn.send(&request).await.is_err() {
+                    break;
+                }
                 // println!("Sent GET_CONNECTED_NODES_REQUEST to seed: {:?}", seed_node);
 
-                // Wait for the response
-                let mut buffer = [0; 1024];
-                if let Ok(n) = stream.read(&mut buffer).await {
-                    let response = String::from_utf8_lossy(&buffer[..n]);
-                    // gets the ips c onnected to the seed other than the current peer.
-                    let connected_nodes: Vec<String> = Peer::_extract_nodes(&response);
-
-                    // Update the connected nodes list if we get other peer IPs.
-                    if connected_nodes[0].len() > 0{
-                        self.connected_nodes.extend(connected_nodes);
+                // gets the ips connected to the seed other than the current peer.
+                match timeout(SEED_TIMEOUT, conn.recv()).await {
+                    Ok(Ok(Message::ConnectedNodes { total, nodes, .. })) => {
+                        offset += nodes.len() as u32;
+                        let page_empty = nodes.is_empty();
+                        peer_list.extend(nodes);
+                        // Stop once the seed has returned every node.
+                        if page_empty || offset >= total {
+                            break;
+                        }
+                    }
+                    Ok(Ok(Message::Reject { addr, reason })) => {
+                        println!("Peer@{}: Peer list refused by seed {}: {}", local_addr, addr, reason);
+                        break;
                     }
+                    _ => break,
                 }
-
-            } else {
-                eprintln!("Peer@{}: Failed to connect to {:?}", self.local_addr, seed_node);
             }
         }
+        peer_list
     }
 
-    // Sends a DEAD_NODE message to the seed nodes
-    pub async fn declare_node_dead(&mut self, dead_node : String){
-        for seed_node in &self.seed_nodes {
-            match TcpStream::connect(seed_node).await {
-                Ok(mut stream) => {
+    // Sends a DEAD_NODE message to the seed nodes, without holding the peer's lock.
+    pub async fn declare_node_dead(transport: &Transport, local_addr: &str, seed_nodes: &[String], dead_node: &str) {
+        for seed_node in seed_nodes {
+            match timeout(SEED_TIMEOUT, transport.connect(seed_node)).await {
+                Ok(Ok(mut conn)) => {
                     // Send GET_CONNECTED_NODES_REQUEST message to seed
-                    let response = format!("{}|{}|{}|{}",
-                        DEAD_NODE_MESSAGE, dead_node, &self.elapsed_time(), &self.local_addr);
-                    if let Err(err) = stream.write_all(response.as_bytes()).await {
+                    let request = Message::DeadNode {
+                        dead_node: dead_node.to_string(),
+                        timestamp: now_ms(),
+                        reporter: local_addr.to_string(),
+                    };
+                    if let Err(err) = conn.send(&request).await {
                         eprintln!("Failed to send DEAD_NODE request to seed {:?}: {}", seed_node, err);
                     }else{
-                        println!("Peer@{}: {}", self.local_addr, response);
+                        println!("Peer@{}: {:?}", local_addr, request);
                     }
                 },
-                Err(err) => {
+                Ok(Err(err)) => {
                     eprintln!("Failed to connect to {:?}: {}", seed_node, err);
                 }
+                Err(_) => {
+                    eprintln!("Failed to connect to {:?}: timed out", seed_node);
+                }
             }
         }
     }
 
-    // Returns the elapsed time since the creation time this acts as the local timestamp.
-    pub fn elapsed_time(&self) -> String {
-        let current_time = Utc::now();
-        let duration = current_time.signed_duration_since(self.creation_time);
+    // Tells the seeds and connected nodes that this peer is leaving the network, and waits for them
+    // to acknowledge it. They connect back to this peer to check the LEAVE, so the caller must keep
+    // the listener running until this returns.
+    pub async fn leave_network(&mut self) {
+        let message = Message::Leave {
+            addr: self.local_addr.clone(),
+            timestamp: now_ms(),
+        };
+        // every node is told at once, so a silent one only delays the departure by one timeout.
+        let mut acks = Vec::new();
+        for node in self.seed_nodes.iter().chain(self.connected_nodes.iter()) {
+            let (transport, node, message) = (self.transport.clone(), node.clone(), message.clone());
+            acks.push(tokio::spawn(async move {
+                let leave = async {
+                    let mut conn = transport.connect(&node).await?;
+                    conn.send(&message).await?;
+                    conn.recv().await
+                };
+                match timeout(SEED_TIMEOUT, leave).await {
+                    Ok(Ok(_ack)) => {}
+                    Ok(Err(err)) => eprintln!("Failed to send LEAVE to {:?}: {}", node, err),
+                    Err(_) => eprintln!("LEAVE to {:?} wasn't acknowledged in time", node),
+                }
+            }));
+        }
+        for ack in acks {
+            let _ = ack.await;
+        }
+        // stop liveness checks towards the old neighbours.
+        self.connected_nodes.clear();
+        self.pool.close_all();
+        println!("Peer@{}: Left the network.", self.local_addr);
+    }
 
-        let _hours = duration.num_hours();
-        let minutes = duration.num_minutes() % 60;
-        let seconds = duration.num_seconds() % 60;
-        let milliseconds = (duration.num_milliseconds() % 1000).abs();
+}
 
-        // Format hours, minutes, seconds, and milliseconds using num-format
-        format!("{:02}:{:02}:{:03}",minutes, seconds, milliseconds)
+// Path of the signing key of the peer listening on `addr`, its node ID is written next to it.
+pub fn key_path(keys_dir: &Path, addr: &str) -> PathBuf {
+    keys_dir.join(format!("peer_{}.key", addr.replace(':', "_")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn node_id(dir: &Path, addr: &str) -> Vec<u8> {
+        NodeKey::load_or_generate(&key_path(dir, addr)).unwrap().node_id()
+    }
+
+    fn peer_trusting(dir: &Path) -> Peer {
+        let key = NodeKey::load_or_generate(&key_path(dir, "127.0.0.1:9090")).unwrap();
+        let config = PeerConfig { trusted_keys: vec![dir.to_path_buf()], ..PeerConfig::default() };
+        Peer::new(1, "127.0.0.1:9090".to_string(), Vec::new(), config, key)
+    }
+    #[test]
+    fn nodes_declared_dead_are_candidates_again_after_the_quarantine() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(dir.path());
+        let listed = HashSet::from(["127.0.0.1:9091".to_string(), "127.0.0.1:9092".to_string()]);
+        peer.declared_dead.insert("127.0.0.1:9091".to_string(), Instant::now());
+        assert_eq!(peer.neighbour_candidates(listed.clone()), vec!["127.0.0.1:9092".to_string()]);
+        // once the quarantine is over a seed still listing the node brings it back.
+        let quarantine = peer.config.dead_node_quarantine;
+        peer.declared_dead.insert("127.0.0.1:9091".to_string(), Instant::now() - quarantine);
+        let mut candidates = peer.neighbour_candidates(listed);
+        candidates.sort();
+        assert_eq!(candidates, vec!["127.0.0.1:9091".to_string(), "127.0.0.1:9092".to_string()]);
+        assert!(peer.declared_dead.is_empty());
+    }
+
+    #[test]
+    fn a_newer_alive_update_clears_a_declared_death() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(dir.path());
+        peer.record_member("127.0.0.1:9091", MemberState::Dead);
+        peer.declared_dead.insert("127.0.0.1:9091".to_string(), Instant::now());
+        // an Alive at the same incarnation doesn't override the death.
+        let stale = MembershipUpdate { addr: "127.0.0.1:9091".to_string(), state: MemberState::Alive, incarnation: 0 };
+        peer.apply_membership(vec![stale]);
+        assert!(peer.declared_dead.contains_key("127.0.0.1:9091"));
+        let refuted = MembershipUpdate { addr: "127.0.0.1:9091".to_string(), state: MemberState::Alive, incarnation: 1 };
+        peer.apply_membership(vec![refuted]);
+        assert!(peer.declared_dead.is_empty());
+        assert_eq!(peer.neighbour_candidates(HashSet::new()), vec!["127.0.0.1:9091".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn only_helpers_asked_to_probe_a_suspect_may_vouch_for_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(dir.path());
+        for neighbour in ["127.0.0.1:9091", "127.0.0.1:9092", "127.0.0.1:9093"] {
+            peer.add_neighbour(neighbour.to_string());
+        }
+        let now = Instant::now();
+        assert!(!peer.indirect_ack("127.0.0.1:9091", "127.0.0.1:9093", now));
+        // the other two neighbours are asked to probe the silent one.
+        peer.update_suspects(vec!["127.0.0.1:9093".to_string()], now);
+        assert!(!peer.indirect_ack("127.0.0.1:9999", "127.0.0.1:9093", now));
+        assert!(!peer.indirect_ack("127.0.0.1:9093", "127.0.0.1:9093", now));
+        assert!(peer.indirect_ack("127.0.0.1:9091", "127.0.0.1:9093", now));
+        // once the suspicion is cleared the probe is no longer outstanding.
+        peer.update_suspects(Vec::new(), now);
+        assert!(!peer.indirect_ack("127.0.0.1:9092", "127.0.0.1:9093", now));
+    }
+
+    #[test]
+    fn only_neighbours_may_ask_for_probes_of_known_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(dir.path());
+        peer.connected_nodes.insert("127.0.0.1:9091".to_string());
+        peer.membership.discover("127.0.0.1:9092");
+        assert!(peer.accepts_ping_req("127.0.0.1:9091", "127.0.0.1:9092"));
+        assert!(!peer.accepts_ping_req("127.0.0.1:9099", "127.0.0.1:9092"));
+        assert!(!peer.accepts_ping_req("127.0.0.1:9091", "10.0.0.1:80"));
+        assert!(!peer.accepts_ping_req("127.0.0.1:9091", "127.0.0.1:9090"));
+        peer.record_member("127.0.0.1:9092", MemberState::Dead);
+        assert!(!peer.accepts_ping_req("127.0.0.1:9091", "127.0.0.1:9092"));
+    }
+
+    #[test]
+    fn claims_at_the_last_incarnation_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(dir.path());
+        let suspect = MembershipUpdate { addr: peer.local_addr.clone(), state: MemberState::Suspect, incarnation: 4 };
+        peer.apply_membership(vec![suspect]);
+        assert_eq!(peer.incarnation, 5);
+        let dead = MembershipUpdate { addr: peer.local_addr.clone(), state: MemberState::Dead, incarnation: u64::MAX };
+        peer.apply_membership(vec![dead]);
+        assert_eq!(peer.incarnation, 5);
+        // later claims are still refuted.
+        let suspect = MembershipUpdate { addr: peer.local_addr.clone(), state: MemberState::Suspect, incarnation: 5 };
+        peer.apply_membership(vec![suspect]);
+        assert_eq!(peer.incarnation, 6);
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn gossip_must_be_signed_by_the_node_id_registered_for_its_origin() {
+        let trusted = tempfile::tempdir().unwrap();
+        let untrusted = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(trusted.path());
+        let origin_id = node_id(trusted.path(), "127.0.0.1:9091");
+        assert_eq!(peer.check_origin_id("127.0.0.1:9091", &origin_id, "127.0.0.1:9092", None), Ok(()));
+        // another trusted peer can't sign in the origin's name.
+        let neighbour_id = node_id(trusted.path(), "127.0.0.1:9092");
+        let result = peer.check_origin_id("127.0.0.1:9091", &neighbour_id, "127.0.0.1:9092", None);
+        assert!(result.unwrap_err().contains("another one is registered"));
+        let stranger_id = node_id(untrusted.path(), "127.0.0.1:9094");
+        let result = peer.check_origin_id("127.0.0.1:9094", &stranger_id, "127.0.0.1:9092", None);
+        assert!(result.unwrap_err().contains("no node ID"));
+        let result = peer.check_origin_id("../127.0.0.1:9091", &origin_id, "127.0.0.1:9092", None);
+        assert!(result.unwrap_err().contains("not a valid origin address"));
+    }
+
+    #[test]
+    fn key_files_are_read_at_most_once_per_interval() {
+        let trusted = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(trusted.path());
+        let later = tempfile::tempdir().unwrap();
+        let later_id = node_id(later.path(), "127.0.0.1:9093");
+        assert!(peer.check_origin_id("127.0.0.1:9093", &later_id, "127.0.0.1:9093", None).is_err());
+        // a key written right after a miss isn't read until the interval is over.
+        std::fs::copy(key_path(later.path(), "127.0.0.1:9093").with_extension("pub"),
+            key_path(trusted.path(), "127.0.0.1:9093").with_extension("pub")).unwrap();
+        assert!(peer.check_origin_id("127.0.0.1:9093", &later_id, "127.0.0.1:9093", None).is_err());
+        peer.key_lookups.insert("127.0.0.1:9093".to_string(), Instant::now() - KEY_LOOKUP_INTERVAL);
+        assert_eq!(peer.check_origin_id("127.0.0.1:9093", &later_id, "127.0.0.1:9093", None), Ok(()));
+    }
+
+    #[test]
+    fn nobody_else_may_sign_in_this_peers_name() {
+        let trusted = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(trusted.path());
+        let own_id = peer.key.node_id();
+        assert_eq!(peer.check_origin_id("127.0.0.1:9090", &own_id, "127.0.0.1:9092", None), Ok(()));
+        let other_id = node_id(trusted.path(), "127.0.0.1:9092");
+        let result = peer.check_origin_id("127.0.0.1:9090", &other_id, "127.0.0.1:9092", None);
+        assert!(result.unwrap_err().contains("this peer's address"));
+    }
+
+    #[test]
+    fn gossip_sent_by_its_origin_must_match_the_connection() {
+        let trusted = tempfile::tempdir().unwrap();
+        let mut peer = peer_trusting(trusted.path());
+        let origin_id = node_id(trusted.path(), "127.0.0.1:9091");
+        let forwarder_id = node_id(trusted.path(), "127.0.0.1:9092");
+        assert_eq!(peer.check_origin_id("127.0.0.1:9091", &origin_id, "127.0.0.1:9091", Some(&origin_id)), Ok(()));
+        let result = peer.check_origin_id("127.0.0.1:9091", &origin_id, "127.0.0.1:9091", Some(&forwarder_id));
+        assert!(result.unwrap_err().contains("another node ID than its connection"));
+        // a forwarded copy arrives over the forwarder's connection.
+        assert_eq!(peer.check_origin_id("127.0.0.1:9091", &origin_id, "127.0.0.1:9092", Some(&forwarder_id)), Ok(()));
+    }
+}
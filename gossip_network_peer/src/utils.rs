@@ -1,4 +1,4 @@
-/// Contains the utils
+//! Contains the utils
 
 // importing necessary packages
 use std::{collections::HashSet, error::Error};
@@ -17,30 +17,6 @@ pub fn get_ips(file_path: &str) ->  Result<Vec<String>, Box<dyn Error>> {
     Ok(ip)
 }
 
-// returns the time in miliseconds from a string
-pub fn parse_and_convert_to_ms(time_string: &str) -> i32 {
-    // Split the string and collect into a Vec
-    let parts: Vec<&str> = time_string.split(":").collect();
-    // Ensure correct format
-    if parts.len() != 3 {
-        panic!("Invalid time format: expected MM::SS::MS");
-    }
-
-    // Parse each part and handle potential parsing errors
-    let minutes = parts[0].parse::<i32>().unwrap();
-    let seconds = parts[1].parse::<i32>().unwrap();
-    let milliseconds = parts[2].parse::<i32>().unwrap();
-
-    // Calculate total milliseconds
-    let total_milliseconds = minutes * 60 * 1000 + seconds * 1000 + milliseconds;
-
-    // Handle overflow
-    if total_milliseconds > i32::MAX {
-        panic!("Overflow: total milliseconds exceed u64 limit");
-    }
-    total_milliseconds
-}
-
 // logic for selecting k distinct nodes at random from a Vector.
 pub fn select_k_nodes(seeds: Vec<String> , k: usize) -> Vec<String> {
     let n = seeds.len();
@@ -57,5 +33,5 @@ pub fn select_k_nodes(seeds: Vec<String> , k: usize) -> Vec<String> {
         .iter()
         .map(|&index| seeds[index].clone())
         .collect();
-    return result_vector
+    result_vector
 }
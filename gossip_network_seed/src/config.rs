@@ -0,0 +1,110 @@
+//! Tunable settings for Seed Node
+
+// Including the packages.
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use ipnet::IpNet;
+use gossip_protocol::{NodeKey, Transport, TransportMode};
+
+// Settings shared by every seed, read from `settings.txt`.
+#[derive(Clone, Debug)]
+pub struct SeedConfig {
+    pub dead_node_quorum: usize, // distinct reporters needed to remove a node without probing it.
+    pub dead_report_window: Duration, // how long a DEAD_NODE report counts towards the quorum.
+    pub probe_timeout: Duration, // how long to wait for a probed node to answer.
+    pub health_probe_interval: Duration, // interval between two health probes of every registered peer.
+    pub health_failure_threshold: u32, // consecutive unanswered health probes before a peer expires.
+    pub sync_interval: Duration, // interval between two membership exchanges with the other seeds.
+    pub store_dir: Option<String>, // directory for membership snapshots, persistence is off when unset.
+    pub store_snapshot_interval: Duration, // interval between two membership snapshots.
+    pub store_max_age: Duration, // entries not seen for longer than this are dropped on load.
+    pub keys_dir: String, // directory holding the signing key of every seed.
+    pub transport: TransportMode, // plain TCP or Noise encrypted connections.
+    pub trusted_keys: Vec<PathBuf>, // directories of the node IDs the Noise transport accepts.
+    pub admission_allowlist: Vec<IpNet>, // networks peers may join from, any network when empty.
+    pub join_tokens: Vec<String>, // tokens a peer must present to join, none needed when empty.
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        SeedConfig {
+            dead_node_quorum: 2,
+            dead_report_window: Duration::from_secs(60),
+            probe_timeout: Duration::from_secs(5),
+            health_probe_interval: Duration::from_secs(10),
+            health_failure_threshold: 3,
+            sync_interval: Duration::from_secs(5),
+            store_dir: None,
+            store_snapshot_interval: Duration::from_secs(30),
+            store_max_age: Duration::from_secs(10 * 60),
+            keys_dir: "./keys".to_string(),
+            transport: TransportMode::Plain,
+            trusted_keys: vec![PathBuf::from("./keys")],
+            admission_allowlist: Vec::new(),
+            join_tokens: Vec::new(),
+        }
+    }
+}
+
+impl SeedConfig {
+    // Builds the config from parsed settings, missing or invalid keys keep their default.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Self {
+        let default = SeedConfig::default();
+        SeedConfig {
+            dead_node_quorum: parse_or(settings, "dead_node_quorum", default.dead_node_quorum),
+            dead_report_window: Duration::from_secs(
+                parse_or(settings, "dead_report_window_secs", default.dead_report_window.as_secs())),
+            probe_timeout: Duration::from_secs(
+                parse_or(settings, "probe_timeout_secs", default.probe_timeout.as_secs())),
+            health_probe_interval: Duration::from_secs(
+                parse_or(settings, "health_probe_interval_secs", default.health_probe_interval.as_secs())),
+            health_failure_threshold: parse_or(settings, "health_failure_threshold", default.health_failure_threshold),
+            sync_interval: Duration::from_secs(
+                parse_or(settings, "sync_interval_secs", default.sync_interval.as_secs())),
+            store_dir: settings.get("store_dir").cloned(),
+            store_snapshot_interval: Duration::from_secs(
+                parse_or(settings, "store_snapshot_interval_secs", default.store_snapshot_interval.as_secs())),
+            store_max_age: Duration::from_secs(
+                parse_or(settings, "store_max_age_secs", default.store_max_age.as_secs())),
+            keys_dir: settings.get("keys_dir").cloned().unwrap_or(default.keys_dir),
+            transport: parse_or(settings, "transport", default.transport),
+            // a comma separated list of directories.
+            trusted_keys: settings.get("trusted_keys")
+                .map(|dirs| dirs.split(',').map(PathBuf::from).collect())
+                .unwrap_or(default.trusted_keys),
+            admission_allowlist: parse_list(settings, "admission_allowlist"),
+            join_tokens: parse_list(settings, "join_tokens"),
+        }
+    }
+
+    // Builds the transport used by the seed owning `key`.
+    pub fn transport(&self, key: &NodeKey) -> Transport {
+        match self.transport {
+            TransportMode::Plain => Transport::plain(),
+            TransportMode::Noise => Transport::noise(key, self.trusted_keys.clone()),
+        }
+    }
+}
+
+// Parses a comma separated setting, skipping invalid entries.
+fn parse_list<T: FromStr>(settings: &HashMap<String, String>, key: &str) -> Vec<T> {
+    let Some(value) = settings.get(key) else {
+        return Vec::new();
+    };
+    value.split(',')
+        .filter_map(|entry| entry.parse().map_err(|_| println!("Invalid value for {}: {}", key, entry)).ok())
+        .collect()
+}
+
+// Parses a single setting, falling back to the default when absent or invalid.
+fn parse_or<T: FromStr>(settings: &HashMap<String, String>, key: &str, default: T) -> T {
+    match settings.get(key) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            println!("Invalid value for {}: {}", key, value);
+            default
+        }),
+        None => default,
+    }
+}
@@ -1,12 +1,18 @@
-/// Main code for Seed Node
+//! Main code for Seed Node
 
 mod tokio_seed;
 mod file_reader;
+mod membership;
+mod config;
+mod store;
 
 use tokio_seed::Seed;
+use config::SeedConfig;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::spawn;
+use gossip_protocol::NodeKey;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -16,12 +22,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("IP Addresses: {:?}", ips);
     println!("Ports: {:?}", ports);
+
+    // Reads the tunable settings shared by every seed from the settings.txt file
+    let settings_path = "./src/settings.txt";
+    let config = match file_reader::read_settings(settings_path) {
+        Ok(settings) => SeedConfig::from_settings(&settings),
+        Err(err) => {
+            println!("Using default settings, could not read {}: {}", settings_path, err);
+            SeedConfig::default()
+        }
+    };
     
+    let keys_dir = Path::new(&config.keys_dir);
+
+    let seed_addrs: Vec<String> = ips.iter().zip(&ports)
+        .map(|(ip, port)| format!("{}:{}", ip, port))
+        .collect();
+
     let mut handles = vec![];
-    let mut seed_no: i32 = 1;
     // Starts a listener for each SEed.
     for i in 0..ips.len() {
-        let seed = Arc::new(Mutex::new(Seed::new(seed_no)));
+        let seed_no = i as i32 + 1;
+        // Every other seed in the config is replicated with.
+        let other_seeds: Vec<String> = seed_addrs.iter()
+            .filter(|addr| **addr != seed_addrs[i])
+            .cloned()
+            .collect();
+        // each seed keeps its key across restarts, so its node ID stays the same.
+        let key_path = keys_dir.join(format!("seed_{}.key", seed_addrs[i].replace(':', "_")));
+        let key = NodeKey::load_or_generate(&key_path)?;
+        let transport = config.transport(&key);
+        let seed = Arc::new(Mutex::new(
+            Seed::new(seed_no, seed_addrs[i].clone(), other_seeds, config.clone(), key, transport)));
         let seed_ip = ips[i].clone();
         let seed_port = ports[i].clone();
         // Creating a shared reference to the seed
@@ -30,8 +62,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let handle = spawn(async move {
             Seed::start_listener(seed_clone, seed_ip, seed_port).await;
         });
+        // spawning a thread for replicating membership with the other seeds.
+        spawn(Seed::start_sync(Arc::clone(&seed)));
+        // spawning a thread for probing the health of registered peers.
+        spawn(Seed::start_health_probe(Arc::clone(&seed)));
+        // spawning a thread for writing membership snapshots to disk.
+        spawn(Seed::start_persistence(Arc::clone(&seed)));
         
-        seed_no+=1;
         handles.push(handle);
     }
     
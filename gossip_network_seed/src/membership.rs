@@ -0,0 +1,162 @@
+//! Replicated membership view kept by every Seed
+
+// Including the packages.
+use std::collections::HashMap;
+use gossip_protocol::{now_ms, MemberRecord};
+
+// How long a removal is remembered so other seeds learn about it before it is forgotten.
+const TOMBSTONE_TTL_MS: u64 = 10 * 60 * 1000;
+// How far ahead of the local clock a replicated change may be dated, later ones are ignored.
+const MAX_CLOCK_SKEW_MS: u64 = 60 * 1000;
+
+// A peer's replicated record together with this seed's own bookkeeping.
+#[derive(Clone, Debug)]
+pub struct MemberEntry {
+    pub record: MemberRecord,
+    pub joined_at: u64, // when the peer joined, in milliseconds since the UNIX epoch.
+    pub last_seen: u64, // when the peer last joined or answered a probe.
+}
+
+// Peers registered with the seed network, including recently removed ones.
+#[derive(Default)]
+pub struct Membership {
+    entries: HashMap<String, MemberEntry>, // latest entry for each peer address.
+}
+
+impl Membership {
+    // Registers a peer, returns true if it was not already alive.
+    pub fn insert(&mut self, addr: String) -> bool {
+        let now = now_ms();
+        let was_alive = self.contains(&addr);
+        let joined_at = match self.entries.get(&addr) {
+            Some(entry) if was_alive => entry.joined_at,
+            _ => now,
+        };
+        let record = MemberRecord { addr: addr.clone(), updated_at: now, alive: true };
+        self.entries.insert(addr, MemberEntry { record, joined_at, last_seen: now });
+        !was_alive
+    }
+
+    // Removes a peer by leaving a tombstone, returns true if it was alive.
+    pub fn remove(&mut self, addr: &str) -> bool {
+        match self.entries.get_mut(addr) {
+            Some(entry) if entry.record.alive => {
+                entry.record.updated_at = now_ms();
+                entry.record.alive = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Records that a registered peer is still reachable.
+    pub fn mark_seen(&mut self, addr: &str) {
+        if let Some(entry) = self.entries.get_mut(addr) {
+            if entry.record.alive {
+                entry.last_seen = now_ms();
+            }
+        }
+    }
+
+    // Checks whether a peer is currently registered.
+    pub fn contains(&self, addr: &str) -> bool {
+        self.entries.get(addr).is_some_and(|entry| entry.record.alive)
+    }
+
+    // Iterates over the addresses of the registered peers.
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.entries.values().filter(|entry| entry.record.alive).map(|entry| &entry.record.addr)
+    }
+
+    // Iterates over every entry, including removed peers.
+    pub fn entries(&self) -> impl Iterator<Item = &MemberEntry> {
+        self.entries.values()
+    }
+
+    // Returns every record so it can be sent to another seed.
+    pub fn records(&self) -> Vec<MemberRecord> {
+        self.entries.values().map(|entry| entry.record.clone()).collect()
+    }
+
+    // Merges records received from another seed, returns how many changed the local view.
+    // A record dated far in the future would win every later change, so it is ignored.
+    pub fn merge(&mut self, records: Vec<MemberRecord>) -> usize {
+        let latest = now_ms().saturating_add(MAX_CLOCK_SKEW_MS);
+        let mut changed = 0;
+        for record in records {
+            if record.updated_at > latest {
+                continue;
+            }
+            let entry = match self.entries.get(&record.addr) {
+                Some(local) if is_newer(&record, &local.record) => MemberEntry {
+                    joined_at: if local.record.alive && record.alive { local.joined_at } else { record.updated_at },
+                    last_seen: local.last_seen.max(record.updated_at),
+                    record,
+                },
+                Some(_) => continue,
+                None => MemberEntry { joined_at: record.updated_at, last_seen: record.updated_at, record },
+            };
+            self.entries.insert(entry.record.addr.clone(), entry);
+            changed += 1;
+        }
+        changed
+    }
+
+    // Restores an entry loaded from disk unless a newer one is already known.
+    pub fn restore(&mut self, entry: MemberEntry) {
+        let newer = match self.entries.get(&entry.record.addr) {
+            Some(local) => is_newer(&entry.record, &local.record),
+            None => true,
+        };
+        if newer {
+            self.entries.insert(entry.record.addr.clone(), entry);
+        }
+    }
+
+    // Forgets removals that every seed has had time to learn about.
+    pub fn prune_tombstones(&mut self) {
+        let now = now_ms();
+        self.entries.retain(|_, entry| {
+            entry.record.alive || now.saturating_sub(entry.record.updated_at) < TOMBSTONE_TTL_MS
+        });
+    }
+}
+
+// Checks whether `record` should replace `local`, the newest change wins and a removal wins a tie.
+fn is_newer(record: &MemberRecord, local: &MemberRecord) -> bool {
+    record.updated_at > local.updated_at
+        || (record.updated_at == local.updated_at && local.alive && !record.alive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(addr: &str, updated_at: u64, alive: bool) -> MemberRecord {
+        MemberRecord { addr: addr.to_string(), updated_at, alive }
+    }
+
+    #[test]
+    fn newer_records_win_and_removals_win_ties() {
+        let mut membership = Membership::default();
+        let now = now_ms();
+        assert_eq!(membership.merge(vec![record("127.0.0.1:9090", now, true)]), 1);
+        assert_eq!(membership.merge(vec![record("127.0.0.1:9090", now - 1, false)]), 0);
+        assert!(membership.contains("127.0.0.1:9090"));
+        assert_eq!(membership.merge(vec![record("127.0.0.1:9090", now, false)]), 1);
+        assert!(!membership.contains("127.0.0.1:9090"));
+    }
+
+    #[test]
+    fn records_dated_far_in_the_future_are_ignored() {
+        let mut membership = Membership::default();
+        membership.insert("127.0.0.1:9090".to_string());
+        let tombstone = record("127.0.0.1:9090", u64::MAX, false);
+        assert_eq!(membership.merge(vec![tombstone]), 0);
+        assert!(membership.contains("127.0.0.1:9090"));
+        // a removal dated within the allowed skew is applied.
+        let tombstone = record("127.0.0.1:9090", now_ms() + MAX_CLOCK_SKEW_MS / 2, false);
+        assert_eq!(membership.merge(vec![tombstone]), 1);
+        assert!(!membership.contains("127.0.0.1:9090"));
+    }
+}
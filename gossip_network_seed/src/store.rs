@@ -0,0 +1,147 @@
+//! On-disk snapshots of a Seed's membership
+
+// Including the packages.
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use gossip_protocol::{now_ms, MemberRecord};
+
+// Including the files.
+use crate::membership::{MemberEntry, Membership};
+
+// Snapshot file of a single seed inside the store directory.
+// Each line holds `addr joined_at last_seen updated_at alive`, timestamps in milliseconds.
+pub struct MembershipStore {
+    path: PathBuf,
+}
+
+impl MembershipStore {
+    // Opens the store of the seed listening on `local_addr`, creating the directory if needed.
+    pub fn new(dir: &str, local_addr: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file_name = format!("seed_{}.snapshot", local_addr.replace(':', "_"));
+        Ok(MembershipStore { path: PathBuf::from(dir).join(file_name) })
+    }
+
+    // Loads the last snapshot, dropping entries not seen for longer than `max_age`.
+    pub fn load(&self, max_age: Duration) -> io::Result<Vec<MemberEntry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let reader = BufReader::new(file);
+        let now = now_ms();
+        let max_age = max_age.as_millis() as u64;
+
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let Some(entry) = parse_entry(&line) else {
+                println!("Invalid line format: {}", line);
+                continue;
+            };
+            if now.saturating_sub(entry.last_seen) <= max_age {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    // Writes a snapshot of the membership, replacing the previous one atomically.
+    pub fn save(&self, membership: &Membership) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        for entry in membership.entries() {
+            writeln!(file, "{} {} {} {} {}",
+                entry.record.addr, entry.joined_at, entry.last_seen,
+                entry.record.updated_at, entry.record.alive)?;
+        }
+        file.sync_all()?;
+        fs::rename(tmp_path, &self.path)
+    }
+}
+
+// Parses one snapshot line.
+fn parse_entry(line: &str) -> Option<MemberEntry> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 5 {
+        return None;
+    }
+    Some(MemberEntry {
+        record: MemberRecord {
+            addr: parts[0].to_string(),
+            updated_at: parts[3].parse().ok()?,
+            alive: parts[4].parse().ok()?,
+        },
+        joined_at: parts[1].parse().ok()?,
+        last_seen: parts[2].parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_in(dir: &tempfile::TempDir) -> MembershipStore {
+        MembershipStore::new(dir.path().to_str().unwrap(), "127.0.0.1:8080").unwrap()
+    }
+
+    fn entry(addr: &str, last_seen: u64) -> MemberEntry {
+        let record = MemberRecord { addr: addr.to_string(), updated_at: last_seen, alive: true };
+        MemberEntry { record, joined_at: last_seen, last_seen }
+    }
+
+    #[test]
+    fn a_saved_snapshot_loads_back_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(&dir);
+        let mut membership = Membership::default();
+        membership.insert("127.0.0.1:9090".to_string());
+        membership.insert("127.0.0.1:9091".to_string());
+        membership.remove("127.0.0.1:9091");
+        store.save(&membership).unwrap();
+
+        let mut saved: Vec<MemberEntry> = membership.entries().cloned().collect();
+        let mut loaded = store.load(Duration::from_secs(600)).unwrap();
+        saved.sort_by(|a, b| a.record.addr.cmp(&b.record.addr));
+        loaded.sort_by(|a, b| a.record.addr.cmp(&b.record.addr));
+        assert_eq!(loaded.len(), 2);
+        for (loaded, saved) in loaded.iter().zip(&saved) {
+            assert_eq!(loaded.record, saved.record);
+            assert_eq!(loaded.joined_at, saved.joined_at);
+            assert_eq!(loaded.last_seen, saved.last_seen);
+        }
+        // the temporary file was renamed over the snapshot.
+        assert!(!store.path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn entries_not_seen_within_the_max_age_are_dropped_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(&dir);
+        let now = now_ms();
+        let mut membership = Membership::default();
+        membership.restore(entry("127.0.0.1:9090", now));
+        membership.restore(entry("127.0.0.1:9091", now - 20 * 60 * 1000));
+        store.save(&membership).unwrap();
+
+        let loaded = store.load(Duration::from_secs(10 * 60)).unwrap();
+        let addrs: Vec<&str> = loaded.iter().map(|entry| entry.record.addr.as_str()).collect();
+        assert_eq!(addrs, vec!["127.0.0.1:9090"]);
+    }
+
+    #[test]
+    fn a_missing_snapshot_loads_empty_and_invalid_lines_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(&dir);
+        assert!(store.load(Duration::from_secs(600)).unwrap().is_empty());
+
+        let now = now_ms();
+        fs::write(&store.path, format!("garbage\n127.0.0.1:9090 {now} {now} {now} true\n")).unwrap();
+        let loaded = store.load(Duration::from_secs(600)).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].record.addr, "127.0.0.1:9090");
+    }
+}
@@ -1,81 +1,477 @@
-/// Contains the Struct and functions for Seed Node
+//! Contains the Struct and functions for Seed Node
 
 // Including the packages.
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind};
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, timeout};
+use gossip_protocol::{keys, now_ms, Connection, ConnectionIdentity, Message, NodeKey, Transport};
 
-// constants for specific requests.
-const JOIN_REQUEST_MESSAGE: &str = "JOIN_REQUEST";
-const GET_CONNECTED_NODES_REQUEST: &str = "GET_CONNECTED_NODES_REQUEST";
-const DEAD_NODE_MESSAGE: &str = "DEAD_NODE";
+// Including the files.
+use crate::config::SeedConfig;
+use crate::membership::Membership;
+use crate::store::MembershipStore;
+
+// How long another seed has to accept a sync connection and answer it.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
 
 // Represents a Seed
 pub struct Seed {
     seed_no: i32, // seed Identifier
-    connected_networks: HashSet<String>, // IPs & Port of Unique peers connected to these seed.
+    local_addr: String, // IP:PORT this seed listens on.
+    seed_nodes: Vec<String>, // IP:PORT of the other seeds to replicate membership with.
+    connected_networks: Membership, // IPs & Port of Unique peers registered with the seed network.
+    config: SeedConfig, // tunable settings.
+    dead_reports: HashMap<String, HashMap<String, Instant>>, // dead node -> reporter -> time of the report.
+    pending_probes: HashMap<String, Instant>, // nodes probed by this seed that haven't answered yet, with the time of the probe.
+    awaiting_health_reply: HashSet<String>, // nodes that haven't answered the latest health probe.
+    health_failures: HashMap<String, u32>, // consecutive health probes each node failed to answer.
+    store: Option<MembershipStore>, // on-disk membership snapshots, if enabled.
+    key: NodeKey, // signing key, its public half is this seed's node ID.
+    seed_ids: HashSet<Vec<u8>>, // node IDs read from keys_dir, the only ones allowed to sync the membership.
+    transport: Arc<Transport>, // opens and accepts this seed's connections.
 }
 
 impl Seed {
-    pub fn new(seed_no: i32) -> Self {
-        Seed {
+    pub fn new(seed_no: i32, local_addr: String, seed_nodes: Vec<String>, config: SeedConfig, key: NodeKey, transport: Transport) -> Self {
+        // Restore the membership from the last snapshot, if persistence is enabled.
+        let mut connected_networks = Membership::default();
+        let store = config.store_dir.as_ref().and_then(|dir| {
+            match MembershipStore::new(dir, &local_addr) {
+                Ok(store) => Some(store),
+                Err(err) => {
+                    eprintln!("Seed #{}: Failed to open membership store in {}: {}", seed_no, dir, err);
+                    None
+                }
+            }
+        });
+        if let Some(store) = &store {
+            match store.load(config.store_max_age) {
+                Ok(entries) => {
+                    println!("Seed #{}: Restored {} membership entries from disk.", seed_no, entries.len());
+                    entries.into_iter().for_each(|entry| connected_networks.restore(entry));
+                }
+                Err(err) => eprintln!("Seed #{}: Failed to load membership snapshot: {}", seed_no, err),
+            }
+        }
+
+        let mut seed = Seed {
             seed_no,
-            connected_networks: HashSet::new(),
+            local_addr,
+            seed_nodes,
+            connected_networks,
+            config,
+            dead_reports: HashMap::new(),
+            pending_probes: HashMap::new(),
+            awaiting_health_reply: HashSet::new(),
+            health_failures: HashMap::new(),
+            store,
+            key,
+            seed_ids: HashSet::new(),
+            transport: Arc::new(transport),
+        };
+        seed.reload_seed_ids();
+        seed
+    }
+
+    // Records a DEAD_NODE report and returns how many distinct reporters agree within the window.
+    // Only registered peers count, a reporter that left or was removed no longer does.
+    // The window is measured on the monotonic clock, so a wall clock jump doesn't expire or revive reports.
+    fn record_dead_report(&mut self, dead_node: &str, reporter: &str, now: Instant) -> usize {
+        let window = self.config.dead_report_window;
+        let members = &self.connected_networks;
+        let reports = self.dead_reports.entry(dead_node.to_string()).or_default();
+        if members.contains(reporter) {
+            reports.insert(reporter.to_string(), now);
+        }
+        reports.retain(|reporter, reported_at| {
+            members.contains(reporter) && now.saturating_duration_since(*reported_at) <= window
+        });
+        reports.len()
+    }
+
+    // Removes a node from the membership and forgets its reports and probes.
+    fn remove_member(&mut self, addr: &str) -> bool {
+        self.dead_reports.remove(addr);
+        self.pending_probes.remove(addr);
+        self.awaiting_health_reply.remove(addr);
+        self.health_failures.remove(addr);
+        self.connected_networks.remove(addr)
+    }
+
+    // Removes a node that has been found dead.
+    fn remove_dead_node(&mut self, dead_node: &str) {
+        if self.remove_member(dead_node) {
+            println!("Seed #{}: Successfully Removed Dead node:[{}]", self.seed_no, dead_node);
+        }
+    }
+
+    // Checks a JOIN_REQUEST against the admission policy, returns the reason if it is refused.
+    fn check_admission(&self, addr: &str, token: Option<&str>) -> Result<(), String> {
+        let ip = addr.parse::<SocketAddr>()
+            .map_err(|_| format!("invalid address {}", addr))?
+            .ip()
+            .to_canonical();
+        let allowlist = &self.config.admission_allowlist;
+        if !allowlist.is_empty() && !allowlist.iter().any(|network| network.contains(&ip)) {
+            return Err(format!("address {} is not in the admission allowlist", ip));
         }
+        let join_tokens = &self.config.join_tokens;
+        if !join_tokens.is_empty() {
+            match token {
+                None => return Err("a join token is required".to_string()),
+                Some(token) if !join_tokens.iter().any(|known| known == token) => {
+                    return Err("invalid join token".to_string());
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Returns a page of the registered peers other than the requester.
+    // Only registered peers are served, so a node refused at admission can't learn who to link with.
+    fn peer_list_page(&self, requester: &str, offset: u32, limit: Option<u32>) -> Message {
+        if !self.connected_networks.contains(requester) {
+            let reason = format!("{} is not a registered peer", requester);
+            return Message::Reject { addr: self.local_addr.clone(), reason };
+        }
+        // Sorted so consecutive pages don't overlap or skip entries.
+        let mut connected_nodes_list: Vec<String> = self.connected_networks
+            .iter()
+            .filter(|node| *node != requester)
+            .cloned()
+            .collect();
+        connected_nodes_list.sort();
+        let total = connected_nodes_list.len() as u32;
+        // Only return the requested page.
+        let nodes: Vec<String> = connected_nodes_list
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect();
+        Message::ConnectedNodes { total, offset, nodes }
     }
+
+    // Checks that a SEED_SYNC comes from another seed, returns the reason if it doesn't.
+    // With the Noise transport the connection must also be authenticated by a seed's node ID.
+    fn check_seed_sync(&mut self, sender: &str, remote_id: Option<&[u8]>) -> Result<(), String> {
+        if !self.seed_nodes.iter().any(|seed_node| seed_node == sender) {
+            return Err(format!("{} is not a seed", sender));
+        }
+        if let Some(remote_id) = remote_id {
+            // the seed keys are only read again for an unknown ID, it may belong to a seed enrolled since.
+            if !self.seed_ids.contains(remote_id) {
+                self.reload_seed_ids();
+            }
+            if !self.seed_ids.contains(remote_id) {
+                return Err(format!("node ID of {} is not a seed key", sender));
+            }
+        }
+        Ok(())
+    }
+
+    // Reads the node IDs of the seed keys in keys_dir.
+    fn reload_seed_ids(&mut self) {
+        match keys::load_trusted_ids(Path::new(&self.config.keys_dir)) {
+            Ok(seed_ids) => self.seed_ids = seed_ids.into_iter().collect(),
+            // no seed key written yet, nothing to trust.
+            Err(err) if err.kind() == ErrorKind::NotFound => self.seed_ids.clear(),
+            Err(err) => eprintln!("Seed #{}: Failed to read the seed keys in {}: {}",
+                self.seed_no, self.config.keys_dir, err),
+        }
+    }
+
+    // Builds the liveness request this seed sends to peers, the reply comes back to its listener.
+    fn liveness_request(&self) -> Message {
+        Message::LivenessRequest {
+            timestamp: now_ms(),
+            addr: self.local_addr.clone(),
+            updates: Vec::new(),
+        }
+    }
+
+    // Sends a liveness request to a node, returns false if it couldn't be delivered.
+    async fn send_liveness_request(transport: &Transport, target_node: &str, request: &Message) -> bool {
+        // The node answers with a LIVENESS_REPLY on a new connection to this seed.
+        match transport.connect(target_node).await {
+            Ok(mut conn) => conn.send(request).await.is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // Sends a liveness request to a node in `pending_probes` and removes it if it doesn't answer in time.
+    async fn probe_dead_node(seed: Arc<Mutex<Seed>>, dead_node: String) {
+        let (transport, request, probe_timeout) = {
+            let seed_guard = seed.lock().await;
+            (seed_guard.transport.clone(), seed_guard.liveness_request(), seed_guard.config.probe_timeout)
+        };
+
+        if Seed::send_liveness_request(&transport, &dead_node, &request).await {
+            sleep(probe_timeout).await;
+        }
+
+        let mut seed_guard = seed.lock().await;
+        if seed_guard.pending_probes.contains_key(&dead_node) {
+            println!("Seed #{}: Probe of node [{}] failed.", seed_guard.seed_no, dead_node);
+            seed_guard.remove_dead_node(&dead_node);
+        }
+    }
+
+    // Periodically probes every registered peer and expires the ones that keep failing to answer.
+    pub async fn start_health_probe(seed: Arc<Mutex<Seed>>) {
+        let transport = seed.lock().await.transport.clone();
+        loop {
+            let (targets, request, interval) = {
+                let mut seed_guard = seed.lock().await;
+                // nodes that didn't answer since the previous round failed that probe.
+                let unanswered: Vec<String> = seed_guard.awaiting_health_reply.drain().collect();
+                for node in unanswered {
+                    let failures = seed_guard.health_failures.entry(node.clone()).or_insert(0);
+                    *failures += 1;
+                    if *failures >= seed_guard.config.health_failure_threshold {
+                        println!("Seed #{}: Node [{}] failed {} health probes, expiring it.",
+                            seed_guard.seed_no, node, seed_guard.config.health_failure_threshold);
+                        seed_guard.remove_dead_node(&node);
+                    }
+                }
+
+                let targets: Vec<String> = seed_guard.connected_networks.iter().cloned().collect();
+                seed_guard.awaiting_health_reply = targets.iter().cloned().collect();
+                (targets, seed_guard.liveness_request(), seed_guard.config.health_probe_interval)
+            };
+
+            for target in targets {
+                let request = request.clone();
+                let transport = transport.clone();
+                tokio::spawn(async move {
+                    Seed::send_liveness_request(&transport, &target, &request).await;
+                });
+            }
+            sleep(interval).await;
+        }
+    }
+
+    // Periodically writes the membership to disk, returns at once if persistence is disabled.
+    pub async fn start_persistence(seed: Arc<Mutex<Seed>>) {
+        loop {
+            let interval = {
+                let seed_guard = seed.lock().await;
+                let Some(store) = &seed_guard.store else {
+                    return;
+                };
+                if let Err(err) = store.save(&seed_guard.connected_networks) {
+                    eprintln!("Seed #{}: Failed to write membership snapshot: {}", seed_guard.seed_no, err);
+                }
+                seed_guard.config.store_snapshot_interval
+            };
+            sleep(interval).await;
+        }
+    }
+
     // handles any incoming requests and responds.
-    async fn handle_connection(seed: Arc<Mutex<Seed>>, mut stream: TcpStream) {
-        // Read from the buffer and parse the request.
-        let mut buffer = [0; 1024];
-        let n = stream.read(&mut buffer).await.expect("Failed to read from stream");
-        let received_message = String::from_utf8_lossy(&buffer[..n]).to_string();
-        let mut message_list: Vec<&str> = received_message.split('|').collect();
-        message_list.iter_mut().for_each(|s| *s = s.trim());
+    // A connection may carry several requests, e.g. liveness replies from a peer's pooled stream.
+    // The sender of every request is checked against the connection it arrived on.
+    async fn handle_connection(seed: Arc<Mutex<Seed>>, stream: TcpStream, peer_addr: SocketAddr) {
+        let transport = seed.lock().await.transport.clone();
+        let mut conn = match transport.accept(stream).await {
+            Ok(conn) => conn,
+            Err(err) => {
+                println!("Seed #{}: Handshake with {} failed: {}", seed.lock().await.seed_no, peer_addr, err);
+                return;
+            }
+        };
+        let mut identity = ConnectionIdentity::new(&conn);
+        loop {
+            // Read a complete frame and decode the request.
+            match conn.recv().await {
+                Ok(message) => {
+                    // nodes checking a connection this seed opened are answered without taking the lock.
+                    if let Some(reply) = transport.dial_check_reply(&message) {
+                        let _ = conn.send(&reply).await;
+                        continue;
+                    }
+                    if let Err(reason) = identity.verify(&message, &transport).await {
+                        println!("Seed #{}: Rejected message from {}: {}",
+                            seed.lock().await.seed_no, identity.peer_addr(), reason);
+                        return;
+                    }
+                    Seed::handle_message(&seed, &mut conn, message).await;
+                }
+                // the other end closed its connection.
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return,
+                Err(err) => {
+                    eprintln!("Failed to read from stream: {}", err);
+                    return;
+                }
+            }
+        }
+    }
 
+    // Responds to a single request received on a connection.
+    async fn handle_message(seed: &Arc<Mutex<Seed>>, conn: &mut Connection, message: Message) {
         // Obtain the lock on seed.
         let mut seed_guard = seed.lock().await;
-        // Handle JOIN REQUEST from peer.
-        if message_list[0] == JOIN_REQUEST_MESSAGE {
-            let peer_addr = message_list[1];
-            // add the peer to the list of connected nodes
-            seed_guard.connected_networks.insert(peer_addr.to_string());
-            println!("Seed #{}: Received JOIN request from {}.", seed_guard.seed_no, peer_addr);
-            // respond to the peer
-            let response = format!("Successfully Connected to {:?}", peer_addr);
-            stream.write_all(response.as_bytes()).await.expect("Failed to write response");
-        }
-        // hadnles GET_CONNECTED_NODES_REQUEST
-        else if message_list[0] == GET_CONNECTED_NODES_REQUEST {
-            let peer_addr = message_list[1];
-            // Extract the list of distinct connected nodes other than the requesting peer.
-            let connected_nodes_list: Vec<String> = seed_guard.connected_networks
-                .iter().cloned()
-                .filter(|node| node!= peer_addr) 
-                .collect();
-            // responsd to the peer
-            let response = format!("Connected Nodes: {:?}", connected_nodes_list);
-            stream.write_all(response.as_bytes()).await.expect("Failed to write response");
-        }
-        // Handles DEAD_NODE_MESSAGE
-        else if message_list[0] == DEAD_NODE_MESSAGE {
-            let dead_node = message_list[1];
-            let reporting_node = message_list[3];
-            // Print the received dead node request and the reporting node.
-            println!("Seed #{}: Dead node:[{}] , reported by:[{}]",
-                    seed_guard.seed_no, dead_node, reporting_node);
-            // remove the dead node from the list of connections.
-            if seed_guard.connected_networks.remove(dead_node) {
-                println!("Seed #{}: Successfully Removed Dead node:[{}]",
-                    seed_guard.seed_no, dead_node);
-            } else {
-                println!("Seed #{}: Node [{}] not found in the list of connected networks.",
-                    seed_guard.seed_no, dead_node);
-            }
-        } else {
-            println!("Unexpected Message\n");
+        match message {
+            // Handle JOIN REQUEST from peer.
+            Message::JoinRequest { addr: peer_addr, token, .. } => {
+                // peers outside the admission policy are told why instead of being registered.
+                let response = match seed_guard.check_admission(&peer_addr, token.as_deref()) {
+                    Ok(()) => {
+                        // add the peer to the list of connected nodes
+                        seed_guard.connected_networks.insert(peer_addr.clone());
+                        println!("Seed #{}: Received JOIN request from {}.", seed_guard.seed_no, peer_addr);
+                        Message::Response { text: format!("Successfully Connected to {:?}", peer_addr) }
+                    }
+                    Err(reason) => {
+                        println!("Seed #{}: Refused JOIN request from {}: {}", seed_guard.seed_no, peer_addr, reason);
+                        Message::Reject { addr: seed_guard.local_addr.clone(), reason }
+                    }
+                };
+                // respond to the peer
+                conn.send(&response).await.expect("Failed to write response");
+            }
+            // hadnles GET_CONNECTED_NODES_REQUEST
+            Message::GetConnectedNodes { addr: peer_addr, offset, limit, .. } => {
+                let response = seed_guard.peer_list_page(&peer_addr, offset, limit);
+                if let Message::Reject { reason, .. } = &response {
+                    println!("Seed #{}: Refused peer list to {}: {}", seed_guard.seed_no, peer_addr, reason);
+                }
+                // responsd to the peer
+                if let Err(err) = conn.send(&response).await {
+                    eprintln!("Seed #{}: Failed to send the peer list to {}: {}", seed_guard.seed_no, peer_addr, err);
+                }
+            }
+            // Handles DEAD_NODE_MESSAGE
+            Message::DeadNode { dead_node, reporter: reporting_node, .. } => {
+                // Print the received dead node request and the reporting node.
+                println!("Seed #{}: Dead node:[{}] , reported by:[{}]",
+                        seed_guard.seed_no, dead_node, reporting_node);
+                if !seed_guard.connected_networks.contains(&dead_node) {
+                    println!("Seed #{}: Node [{}] not found in the list of connected networks.",
+                        seed_guard.seed_no, dead_node);
+                    return;
+                }
+                // the reporter's address is bound to this connection, it must also be a registered peer.
+                if !seed_guard.connected_networks.contains(&reporting_node) {
+                    println!("Seed #{}: Ignored report from [{}] which is not a registered peer.",
+                        seed_guard.seed_no, reporting_node);
+                    return;
+                }
+                // remove the dead node once enough distinct reporters agree.
+                let reporters = seed_guard.record_dead_report(&dead_node, &reporting_node, Instant::now());
+                let quorum = seed_guard.config.dead_node_quorum;
+                if reporters >= quorum {
+                    seed_guard.remove_dead_node(&dead_node);
+                } else {
+                    println!("Seed #{}: Node [{}] reported dead by {}/{} reporters, probing it.",
+                        seed_guard.seed_no, dead_node, reporters, quorum);
+                    // otherwise check the node ourselves, unless a probe is already running.
+                    if !seed_guard.pending_probes.contains_key(&dead_node) {
+                        seed_guard.pending_probes.insert(dead_node.clone(), Instant::now());
+                        tokio::spawn(Seed::probe_dead_node(seed.clone(), dead_node));
+                    }
+                }
+            }
+            // Handles the answer to a probe sent by this seed.
+            Message::LivenessReply { addr, .. } => {
+                seed_guard.connected_networks.mark_seen(&addr);
+                seed_guard.awaiting_health_reply.remove(&addr);
+                seed_guard.health_failures.remove(&addr);
+                if seed_guard.pending_probes.remove(&addr).is_some() {
+                    seed_guard.dead_reports.remove(&addr);
+                    println!("Seed #{}: Probed node [{}] is alive.", seed_guard.seed_no, addr);
+                }
+            }
+            // Handles a peer leaving the network, it is removed at once.
+            Message::Leave { addr, .. } => {
+                if seed_guard.remove_member(&addr) {
+                    println!("Seed #{}: Node [{}] left the network.", seed_guard.seed_no, addr);
+                }
+                // the peer keeps listening until the seeds acknowledge, so its address could be checked.
+                let response = Message::Response { text: format!("{} left", addr) };
+                if let Err(err) = conn.send(&response).await {
+                    eprintln!("Seed #{}: Failed to acknowledge LEAVE from {}: {}", seed_guard.seed_no, addr, err);
+                }
+            }
+            // Handles membership replication from another seed.
+            Message::SeedSync { sender, members } => {
+                // only the other seeds may change the replicated membership.
+                if let Err(reason) = seed_guard.check_seed_sync(&sender, conn.remote_id()) {
+                    println!("Seed #{}: Ignored membership sync from {}: {}", seed_guard.seed_no, sender, reason);
+                    return;
+                }
+                let changed = seed_guard.connected_networks.merge(members);
+                if changed > 0 {
+                    println!("Seed #{}: Synced {} membership changes from seed {}.",
+                        seed_guard.seed_no, changed, sender);
+                }
+                // reply with our own view so the exchange is push-pull.
+                let response = Message::SeedSync {
+                    sender: seed_guard.local_addr.clone(),
+                    members: seed_guard.connected_networks.records(),
+                };
+                if let Err(err) = conn.send(&response).await {
+                    eprintln!("Seed #{}: Failed to answer membership sync from {}: {}", seed_guard.seed_no, sender, err);
+                }
+            }
+            _ => {
+                println!("Unexpected Message\n");
+            }
+        }
+    }
+
+    // Periodically exchanges membership with every other seed so all seeds converge.
+    pub async fn start_sync(seed: Arc<Mutex<Seed>>) {
+        loop {
+            let sync_interval = seed.lock().await.config.sync_interval;
+            sleep(sync_interval).await;
+            let (transport, seed_nodes, request) = {
+                let mut seed_guard = seed.lock().await;
+                seed_guard.connected_networks.prune_tombstones();
+                let request = Message::SeedSync {
+                    sender: seed_guard.local_addr.clone(),
+                    members: seed_guard.connected_networks.records(),
+                };
+                (seed_guard.transport.clone(), seed_guard.seed_nodes.clone(), request)
+            };
+
+            for seed_node in seed_nodes {
+                let (seed, transport, request) = (seed.clone(), transport.clone(), request.clone());
+                // each seed is synced on its own, so a slow one doesn't hold up the others.
+                tokio::spawn(async move {
+                    let exchange = async {
+                        let mut conn = transport.connect(&seed_node).await?;
+                        conn.send(&request).await?;
+                        let reply = conn.recv().await?;
+                        Ok::<_, io::Error>((conn, reply))
+                    };
+                    // A seed that is down or doesn't answer in time is simply retried in the next round.
+                    let Ok(Ok((conn, Message::SeedSync { sender, members }))) = timeout(SYNC_TIMEOUT, exchange).await else {
+                        return;
+                    };
+                    let mut seed_guard = seed.lock().await;
+                    let checked = if sender == seed_node {
+                        seed_guard.check_seed_sync(&sender, conn.remote_id())
+                    } else {
+                        Err(format!("{} answered for {}", seed_node, sender))
+                    };
+                    if let Err(reason) = checked {
+                        println!("Seed #{}: Ignored membership sync from {}: {}", seed_guard.seed_no, seed_node, reason);
+                        return;
+                    }
+                    let changed = seed_guard.connected_networks.merge(members);
+                    if changed > 0 {
+                        println!("Seed #{}: Synced {} membership changes from seed {}.",
+                            seed_guard.seed_no, changed, sender);
+                    }
+                });
+            }
         }
     }
 
@@ -83,14 +479,241 @@ impl Seed {
     pub async fn start_listener(seed: Arc<Mutex<Seed>>, ip: String, port: String) {
         let addr = SocketAddr::new(ip.parse().unwrap(), port.parse().unwrap());
         let listener = TcpListener::bind(&addr).await.expect("Failed to bind listener");
-        println!("Seed #{}: listening on {}:{}", seed.lock().await.seed_no, ip, port);
+        {
+            let seed_guard = seed.lock().await;
+            println!("Seed #{}: listening on {}:{} with node ID {}",
+                seed_guard.seed_no, ip, port, keys::node_id_hex(&seed_guard.key.node_id()));
+        }
         // loop for handling any incoming connections.
         loop {
-            let (stream, _) = listener.accept().await.expect("Failed to accept connection");
+            let (stream, peer_addr) = listener.accept().await.expect("Failed to accept connection");
             let seed_clone = seed.clone();
             tokio::spawn(async move {
-                Seed::handle_connection(seed_clone, stream).await;
+                Seed::handle_connection(seed_clone, stream, peer_addr).await;
             });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a seed that isn't listening, for checks that don't need the network.
+    fn seed_with(keys_dir: &Path, config: SeedConfig) -> Seed {
+        let key = NodeKey::load_or_generate(&keys_dir.join("seed.key")).unwrap();
+        Seed::new(1, "127.0.0.1:8080".to_string(), Vec::new(), config, key, Transport::plain())
+    }
+
+    // Picks a loopback port nothing listens on.
+    async fn free_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    // Starts `count` seeds replicating with each other over plain TCP every 100 ms.
+    async fn start_seeds(keys_dir: &Path, count: usize) -> Vec<Arc<Mutex<Seed>>> {
+        start_seeds_with(keys_dir, count, &[]).await
+    }
+
+    // Starts seeds like `start_seeds` that also list `other_seeds`, ahead of each other.
+    async fn start_seeds_with(keys_dir: &Path, count: usize, other_seeds: &[String]) -> Vec<Arc<Mutex<Seed>>> {
+        let mut addrs = Vec::new();
+        for _ in 0..count {
+            addrs.push(free_addr().await);
+        }
+        let config = SeedConfig {
+            sync_interval: Duration::from_millis(100),
+            keys_dir: keys_dir.display().to_string(),
+            ..SeedConfig::default()
+        };
+        let mut seeds = Vec::new();
+        for (index, addr) in addrs.iter().enumerate() {
+            let other_seeds = other_seeds.iter()
+                .chain(addrs.iter().filter(|other| *other != addr))
+                .cloned()
+                .collect();
+            let key = NodeKey::load_or_generate(&keys_dir.join(format!("seed_{}.key", index))).unwrap();
+            let seed = Arc::new(Mutex::new(
+                Seed::new(index as i32 + 1, addr.clone(), other_seeds, config.clone(), key, Transport::plain())));
+            let (ip, port) = addr.split_once(':').unwrap();
+            tokio::spawn(Seed::start_listener(seed.clone(), ip.to_string(), port.to_string()));
+            tokio::spawn(Seed::start_sync(seed.clone()));
+            seeds.push(seed);
+        }
+        seeds
+    }
+
+    // Waits until every seed agrees on whether `addr` is registered.
+    async fn converge(seeds: &[Arc<Mutex<Seed>>], addr: &str, registered: bool) {
+        let all_agree = async {
+            loop {
+                let mut agree = true;
+                for seed in seeds {
+                    agree &= seed.lock().await.connected_networks.contains(addr) == registered;
+                }
+                if agree {
+                    return;
+                }
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(Duration::from_secs(10), all_agree).await
+            .unwrap_or_else(|_| panic!("seeds didn't agree that {} is registered: {}", addr, registered));
+    }
+
+    #[tokio::test]
+    async fn seeds_converge_on_joins_and_removals() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds(dir.path(), 3).await;
+        seeds[0].lock().await.connected_networks.insert("127.0.0.1:9090".to_string());
+        seeds[1].lock().await.connected_networks.insert("127.0.0.1:9091".to_string());
+        converge(&seeds, "127.0.0.1:9090", true).await;
+        converge(&seeds, "127.0.0.1:9091", true).await;
+
+        seeds[2].lock().await.remove_member("127.0.0.1:9090");
+        converge(&seeds, "127.0.0.1:9090", false).await;
+        converge(&seeds, "127.0.0.1:9091", true).await;
+    }
+
+    #[tokio::test]
+    async fn a_silent_seed_does_not_hold_up_the_others() {
+        // a seed that accepts connections but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent_seed = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds_with(dir.path(), 2, &[silent_seed]).await;
+        seeds[0].lock().await.connected_networks.insert("127.0.0.1:9090".to_string());
+        // well before the silent seed's sync times out.
+        let synced = async {
+            while !seeds[1].lock().await.connected_networks.contains("127.0.0.1:9090") {
+                sleep(Duration::from_millis(50)).await;
+            }
+        };
+        timeout(SYNC_TIMEOUT / 2, synced).await.expect("the silent seed held up the sync");
+    }
+
+    #[tokio::test]
+    async fn only_registered_peers_count_towards_the_dead_node_quorum() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds(dir.path(), 1).await;
+        let mut seed = seeds[0].lock().await;
+        for member in ["127.0.0.1:9090", "127.0.0.1:9091", "127.0.0.1:9092"] {
+            seed.connected_networks.insert(member.to_string());
+        }
+        let now = Instant::now();
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9091", now), 1);
+        // unregistered reporters are not counted, however many there are.
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9999", now), 1);
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "10.0.0.1:9092", now), 1);
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9092", now), 2);
+        // a reporter that left no longer counts.
+        seed.remove_member("127.0.0.1:9091");
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9092", now), 1);
+    }
+
+    #[tokio::test]
+    async fn dead_node_reports_expire_after_the_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds(dir.path(), 1).await;
+        let mut seed = seeds[0].lock().await;
+        for member in ["127.0.0.1:9090", "127.0.0.1:9091", "127.0.0.1:9092"] {
+            seed.connected_networks.insert(member.to_string());
+        }
+        let window = seed.config.dead_report_window;
+        let start = Instant::now();
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9091", start), 1);
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9092", start + window), 2);
+        // an hour later both reports have expired and only the new one counts.
+        let later = start + window + Duration::from_secs(3600);
+        assert_eq!(seed.record_dead_report("127.0.0.1:9090", "127.0.0.1:9091", later), 1);
+    }
+
+    #[tokio::test]
+    async fn only_seeds_may_sync_the_membership() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds(dir.path(), 2).await;
+        let mut seed = seeds[0].lock().await;
+        let other_seed = seed.seed_nodes[0].clone();
+        assert_eq!(seed.check_seed_sync(&other_seed, None), Ok(()));
+        assert!(seed.check_seed_sync("127.0.0.1:9090", None).unwrap_err().contains("not a seed"));
+
+        // with the Noise transport the sender must hold one of the seed keys.
+        let seed_id = NodeKey::load_or_generate(&dir.path().join("seed_1.key")).unwrap().node_id();
+        assert_eq!(seed.check_seed_sync(&other_seed, Some(&seed_id)), Ok(()));
+        let peer_dir = tempfile::tempdir().unwrap();
+        let peer_id = NodeKey::load_or_generate(&peer_dir.path().join("peer.key")).unwrap().node_id();
+        assert!(seed.check_seed_sync(&other_seed, Some(&peer_id)).unwrap_err().contains("not a seed key"));
+    }
+
+    #[test]
+    fn the_allowlist_admits_only_its_networks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SeedConfig { admission_allowlist: vec!["10.0.0.0/8".parse().unwrap()], ..SeedConfig::default() };
+        let seed = seed_with(dir.path(), config);
+        assert_eq!(seed.check_admission("10.1.2.3:9090", None), Ok(()));
+        let result = seed.check_admission("192.168.1.2:9090", None);
+        assert!(result.unwrap_err().contains("not in the admission allowlist"));
+    }
+
+    #[test]
+    fn join_tokens_must_be_presented_and_known() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SeedConfig { join_tokens: vec!["secret".to_string()], ..SeedConfig::default() };
+        let seed = seed_with(dir.path(), config);
+        assert_eq!(seed.check_admission("127.0.0.1:9090", Some("secret")), Ok(()));
+        assert_eq!(seed.check_admission("127.0.0.1:9090", None), Err("a join token is required".to_string()));
+        assert_eq!(seed.check_admission("127.0.0.1:9090", Some("guess")), Err("invalid join token".to_string()));
+    }
+
+    #[test]
+    fn only_registered_peers_get_the_peer_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut seed = seed_with(dir.path(), SeedConfig::default());
+        for member in ["127.0.0.1:9090", "127.0.0.1:9091", "127.0.0.1:9092"] {
+            seed.connected_networks.insert(member.to_string());
+        }
+        let nodes = vec!["127.0.0.1:9091".to_string(), "127.0.0.1:9092".to_string()];
+        assert_eq!(seed.peer_list_page("127.0.0.1:9090", 0, None), Message::ConnectedNodes { total: 2, offset: 0, nodes });
+        // a node that was refused or never joined learns nothing.
+        assert!(matches!(seed.peer_list_page("10.0.0.1:9093", 0, None), Message::Reject { .. }));
+    }
+
+    #[tokio::test]
+    async fn a_leave_is_acknowledged_once_the_peer_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let seeds = start_seeds(dir.path(), 1).await;
+        let seed_addr = seeds[0].lock().await.local_addr.clone();
+        // the leaving peer answers the seed's check that it opened the connection, like its listener does.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = listener.local_addr().unwrap().to_string();
+        let transport = Arc::new(Transport::plain());
+        let serving = transport.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut conn = serving.accept(stream).await.unwrap();
+                while let Ok(message) = conn.recv().await {
+                    if let Some(reply) = serving.dial_check_reply(&message) {
+                        let _ = conn.send(&reply).await;
+                    }
+                }
+            }
+        });
+        seeds[0].lock().await.connected_networks.insert(peer_addr.clone());
+        // give the seed's listener time to start.
+        sleep(Duration::from_millis(100)).await;
+
+        let mut conn = transport.connect(&seed_addr).await.unwrap();
+        conn.send(&Message::Leave { addr: peer_addr.clone(), timestamp: now_ms() }).await.unwrap();
+        let ack = timeout(Duration::from_secs(10), conn.recv()).await.unwrap().unwrap();
+        assert!(matches!(ack, Message::Response { .. }));
+        assert!(!seeds[0].lock().await.connected_networks.contains(&peer_addr));
+    }
+}
@@ -0,0 +1,148 @@
+//! Wall-clock timestamps and hybrid logical clocks carried by messages.
+//!
+//! Timestamps on the wire are full-width milliseconds since the UNIX epoch,
+//! so they never wrap. Nodes measure intervals with `std::time::Instant`
+//! instead, which the wall clock jumping can't disturb.
+//!
+//! Gossip messages are stamped with a hybrid logical clock (Kulkarni et al.).
+//! An HLC timestamp is the highest wall-clock time a node has seen and a
+//! logical counter that orders events within the same millisecond. A node
+//! stamping an event after receiving a message always stamps it higher than
+//! the message, so sorting events from every node by HLC respects causality
+//! while staying close to the wall clock.
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Returns the wall-clock time in milliseconds since the UNIX epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Timestamp of a hybrid logical clock, ordered by wall time then counter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HlcTimestamp {
+    pub wall_ms: u64, // highest wall-clock time seen, in milliseconds since the UNIX epoch.
+    pub logical: u32, // orders events sharing the same wall time.
+}
+
+impl HlcTimestamp {
+    // Returns the timestamp right after this one. A saturated counter carries into the wall time,
+    // so a received counter at its maximum can't overflow the clock.
+    fn next(self) -> HlcTimestamp {
+        match self.logical.checked_add(1) {
+            Some(logical) => HlcTimestamp { wall_ms: self.wall_ms, logical },
+            None => HlcTimestamp { wall_ms: self.wall_ms + 1, logical: 0 },
+        }
+    }
+}
+
+impl fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.wall_ms, self.logical)
+    }
+}
+
+// Hybrid logical clock of one node.
+pub struct HybridClock {
+    last: HlcTimestamp, // latest timestamp issued or observed.
+    max_drift: Duration, // how far ahead of the local wall clock a received timestamp may be.
+}
+
+impl HybridClock {
+    pub fn new(max_drift: Duration) -> Self {
+        HybridClock { last: HlcTimestamp::default(), max_drift }
+    }
+
+    // Stamps a local event, such as creating a message.
+    pub fn now(&mut self) -> HlcTimestamp {
+        let wall_ms = now_ms();
+        self.last = if wall_ms > self.last.wall_ms {
+            HlcTimestamp { wall_ms, logical: 0 }
+        } else {
+            self.last.next()
+        };
+        self.last
+    }
+
+    // Stamps the receipt of a message stamped `remote`, so the receipt orders after it.
+    // Timestamps too far ahead of the local wall clock are refused, or one node with a
+    // wrong clock would drag every clock forward.
+    pub fn observe(&mut self, remote: HlcTimestamp) -> Result<HlcTimestamp, String> {
+        let wall_ms = now_ms();
+        let max_drift_ms = self.max_drift.as_millis() as u64;
+        if remote.wall_ms > wall_ms + max_drift_ms {
+            return Err(format!("timestamp {} is {} ms ahead of the local clock", remote, remote.wall_ms - wall_ms));
+        }
+        let max_wall = wall_ms.max(self.last.wall_ms).max(remote.wall_ms);
+        self.last = match (max_wall == self.last.wall_ms, max_wall == remote.wall_ms) {
+            (true, true) => self.last.max(remote).next(),
+            (true, false) => self.last.next(),
+            (false, true) => remote.next(),
+            (false, false) => HlcTimestamp { wall_ms: max_wall, logical: 0 },
+        };
+        Ok(self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_DRIFT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn local_stamps_always_increase() {
+        let mut clock = HybridClock::new(MAX_DRIFT);
+        let mut previous = clock.now();
+        for _ in 0..10_000 {
+            let stamp = clock.now();
+            assert!(stamp > previous, "{} after {}", stamp, previous);
+            previous = stamp;
+        }
+    }
+
+    #[test]
+    fn a_receipt_orders_after_the_received_stamp() {
+        let mut clock = HybridClock::new(MAX_DRIFT);
+        let local = clock.now();
+        // a sender whose clock is a little ahead drags this clock along.
+        let remote = HlcTimestamp { wall_ms: now_ms() + 1_000, logical: 7 };
+        let received = clock.observe(remote).unwrap();
+        assert!(received > remote && received > local);
+        assert_eq!(received, HlcTimestamp { wall_ms: remote.wall_ms, logical: 8 });
+        assert!(clock.now() > received);
+        // a stamp from the past still orders the receipt after everything seen.
+        let old = HlcTimestamp { wall_ms: 1, logical: 0 };
+        let later = clock.observe(old).unwrap();
+        assert!(later > received);
+    }
+
+    #[test]
+    fn a_saturated_counter_carries_into_the_wall_time() {
+        let mut clock = HybridClock::new(MAX_DRIFT);
+        let remote = HlcTimestamp { wall_ms: now_ms() + 1_000, logical: u32::MAX };
+        let received = clock.observe(remote).unwrap();
+        assert_eq!(received, HlcTimestamp { wall_ms: remote.wall_ms + 1, logical: 0 });
+        assert!(clock.observe(remote).unwrap() > received);
+        // the local counter carries the same way.
+        clock.last = HlcTimestamp { wall_ms: now_ms() + 1_000, logical: u32::MAX };
+        let stamp = clock.now();
+        assert_eq!(stamp, HlcTimestamp { wall_ms: clock.last.wall_ms, logical: 0 });
+        assert!(stamp > remote);
+    }
+
+    #[test]
+    fn stamps_too_far_ahead_are_refused() {
+        let mut clock = HybridClock::new(MAX_DRIFT);
+        let before = clock.now();
+        let remote = HlcTimestamp { wall_ms: now_ms() + 2 * MAX_DRIFT.as_millis() as u64, logical: 0 };
+        assert!(clock.observe(remote).unwrap_err().contains("ahead of the local clock"));
+        // a refused stamp doesn't move the clock.
+        assert!(clock.now().wall_ms < remote.wall_ms);
+        assert!(clock.last > before);
+    }
+}
@@ -0,0 +1,60 @@
+//! Length-prefixed framing used on every Seed and Peer connection.
+//!
+//! Frame layout on the wire:
+//! `[ length: u32 big-endian ][ version: u8 ][ payload ]`
+//! where `length` counts the version byte and the payload.
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::message::Message;
+
+// Version of the wire format, bumped whenever the layout changes.
+pub const PROTOCOL_VERSION: u8 = 4;
+// Upper bound on a single frame so a corrupt length can't exhaust memory.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// Writes a payload to the stream as a single frame.
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let len = payload.len() + 1;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame exceeds maximum length"));
+    }
+    let mut buffer = Vec::with_capacity(len + 4);
+    buffer.extend_from_slice(&(len as u32).to_be_bytes());
+    buffer.push(PROTOCOL_VERSION);
+    buffer.extend_from_slice(payload);
+    writer.write_all(&buffer).await?;
+    writer.flush().await
+}
+
+// Reads the payload of a complete frame from the stream, however many reads that takes.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes).await?;
+    let len = u32::from_be_bytes(len_bytes) as usize;
+    if !(1..=MAX_FRAME_LEN).contains(&len) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid frame length {}", len)));
+    }
+
+    let mut buffer = vec![0u8; len];
+    reader.read_exact(&mut buffer).await?;
+    if buffer[0] != PROTOCOL_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported protocol version {}", buffer[0]),
+        ));
+    }
+    Ok(buffer.split_off(1))
+}
+
+// Encodes a message and writes it as one frame.
+pub async fn send_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> io::Result<()> {
+    write_frame(writer, &message.encode()).await
+}
+
+// Reads one frame and decodes the message it carries.
+pub async fn recv_message<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Message> {
+    let payload = read_frame(reader).await?;
+    Message::decode(&payload).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
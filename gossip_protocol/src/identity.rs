@@ -0,0 +1,202 @@
+//! Sender identity bound to the connection a message arrived on.
+//!
+//! The socket address only tells the ephemeral port the sender dialed from,
+//! so the listening address a node advertises in its first message is
+//! confirmed by connecting back to it. With the Noise transport the node
+//! listening there must hold the node ID that authenticated the connection.
+//! Over plain TCP it is sent a `DIAL_CHECK` and must confirm it opened the
+//! connection, which a node merely sharing its IP can't do. The address is
+//! then bound to the connection, later messages on the same connection must
+//! advertise the same address.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::message::Message;
+use crate::transport::{Connection, Transport};
+
+// How long the node at a claimed address has to confirm it opened a connection.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Identity of the node at the other end of an accepted connection.
+pub struct ConnectionIdentity {
+    peer_addr: SocketAddr, // socket address returned by `accept`.
+    local_addr: SocketAddr, // socket address the other end connected to.
+    remote_id: Option<Vec<u8>>, // node ID authenticated by the Noise handshake.
+    bound_addr: Option<String>, // listening address advertised by the first message.
+}
+
+impl ConnectionIdentity {
+    pub fn new(conn: &Connection) -> Self {
+        ConnectionIdentity {
+            peer_addr: conn.peer_addr(),
+            local_addr: conn.local_addr(),
+            remote_id: conn.remote_id().map(<[u8]>::to_vec),
+            bound_addr: None,
+        }
+    }
+
+    // Socket address of the other end of the connection.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    // Listening address bound to the connection, once a message advertised it.
+    pub fn bound_addr(&self) -> Option<&str> {
+        self.bound_addr.as_deref()
+    }
+
+    // Checks the sender address claimed by a message, returns the reason if it is spoofed.
+    pub async fn verify(&mut self, message: &Message, transport: &Transport) -> Result<(), String> {
+        let Some(claimed) = message.claimed_sender() else {
+            return Ok(());
+        };
+        if let Some(bound) = &self.bound_addr {
+            if bound != claimed {
+                return Err(format!("claims to be {} on a connection bound to {}", claimed, bound));
+            }
+            return Ok(());
+        }
+
+        let claimed_addr: SocketAddr = claimed
+            .parse()
+            .map_err(|_| format!("claims invalid address {}", claimed))?;
+        if claimed_addr.ip().to_canonical() != self.peer_addr.ip().to_canonical() {
+            return Err(format!("claims to be {} but connected from {}", claimed, self.peer_addr));
+        }
+        self.confirm(claimed, transport).await?;
+        self.bound_addr = Some(claimed.to_string());
+        Ok(())
+    }
+
+    // Connects back to the claimed listening address and checks the node there opened this connection.
+    async fn confirm(&self, claimed: &str, transport: &Transport) -> Result<(), String> {
+        let check = async {
+            let mut conn = transport.connect(claimed).await?;
+            if let Some(remote_id) = &self.remote_id {
+                return Ok(conn.remote_id() == Some(remote_id.as_slice()));
+            }
+            let (from, to) = (self.peer_addr.to_string(), self.local_addr.to_string());
+            conn.send(&Message::DialCheck { from: from.clone(), to: to.clone() }).await?;
+            let confirmed = matches!(conn.recv().await?,
+                Message::DialCheckReply { from: reply_from, to: reply_to, dialed: true } if reply_from == from && reply_to == to);
+            Ok::<bool, io::Error>(confirmed)
+        };
+        match timeout(CONFIRM_TIMEOUT, check).await {
+            Ok(Ok(true)) => Ok(()),
+            Ok(Ok(false)) => Err(format!("claims to be {} but that node didn't open the connection", claimed)),
+            Ok(Err(err)) => Err(format!("claims to be {} which can't be reached: {}", claimed, err)),
+            Err(_) => Err(format!("claims to be {} which didn't confirm in time", claimed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use crate::keys::NodeKey;
+
+    // Node listening on loopback that answers the checks of connections it opened, like every listener does.
+    async fn start_node(transport: Transport) -> (String, Arc<Transport>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let transport = Arc::new(transport);
+        let serving = transport.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let transport = serving.clone();
+                tokio::spawn(async move {
+                    let Ok(mut conn) = transport.accept(stream).await else {
+                        return;
+                    };
+                    while let Ok(message) = conn.recv().await {
+                        if let Some(reply) = transport.dial_check_reply(&message) {
+                            let _ = conn.send(&reply).await;
+                        }
+                    }
+                });
+            }
+        });
+        (addr, transport)
+    }
+
+    // Sends a LEAVE claiming `claimed` from `sender` to a verifier and returns what the verifier makes of it.
+    async fn send_claiming(sender: &Transport, claimed: &str, verifier: &Transport) -> Result<(), String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (sent, accepted) = tokio::join!(sender.connect(&addr), async {
+            let (stream, _) = listener.accept().await.unwrap();
+            verifier.accept(stream).await
+        });
+        let (mut sent, mut accepted) = (sent.unwrap(), accepted.unwrap());
+        sent.send(&Message::Leave { addr: claimed.to_string(), timestamp: 0 }).await.unwrap();
+        let message = accepted.recv().await.unwrap();
+        ConnectionIdentity::new(&accepted).verify(&message, verifier).await
+    }
+
+    fn noise_transport(dir: &Path, name: &str) -> Transport {
+        let key = NodeKey::load_or_generate(&dir.join(format!("{}.key", name))).unwrap();
+        Transport::noise(&key, vec![dir.to_path_buf()])
+    }
+
+    #[tokio::test]
+    async fn a_node_can_claim_its_own_address() {
+        let (neighbour_addr, neighbour) = start_node(Transport::plain()).await;
+        assert_eq!(send_claiming(&neighbour, &neighbour_addr, &Transport::plain()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn a_second_local_peer_cannot_claim_a_neighbours_address() {
+        let (neighbour_addr, _neighbour) = start_node(Transport::plain()).await;
+        let (_, impostor) = start_node(Transport::plain()).await;
+        let result = send_claiming(&impostor, &neighbour_addr, &Transport::plain()).await;
+        assert!(result.unwrap_err().contains("didn't open the connection"));
+    }
+
+    #[tokio::test]
+    async fn a_trusted_node_cannot_claim_another_trusted_nodes_address() {
+        let dir = tempfile::tempdir().unwrap();
+        let (neighbour_addr, neighbour) = start_node(noise_transport(dir.path(), "neighbour")).await;
+        let impostor = noise_transport(dir.path(), "impostor");
+        let verifier = noise_transport(dir.path(), "verifier");
+        assert_eq!(send_claiming(&neighbour, &neighbour_addr, &verifier).await, Ok(()));
+        let result = send_claiming(&impostor, &neighbour_addr, &verifier).await;
+        assert!(result.unwrap_err().contains("didn't open the connection"));
+    }
+
+    #[tokio::test]
+    async fn an_unreachable_address_cannot_be_claimed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        let result = send_claiming(&Transport::plain(), &closed_addr, &Transport::plain()).await;
+        assert!(result.unwrap_err().contains("can't be reached"));
+    }
+
+    #[tokio::test]
+    async fn the_bound_address_cannot_change() {
+        let (neighbour_addr, neighbour) = start_node(Transport::plain()).await;
+        let (other_addr, _) = start_node(Transport::plain()).await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let verifier = Transport::plain();
+        let mut sent = neighbour.connect(&addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut accepted = verifier.accept(stream).await.unwrap();
+        let mut identity = ConnectionIdentity::new(&accepted);
+
+        sent.send(&Message::Leave { addr: neighbour_addr.clone(), timestamp: 0 }).await.unwrap();
+        let message = accepted.recv().await.unwrap();
+        assert_eq!(identity.verify(&message, &verifier).await, Ok(()));
+        assert_eq!(identity.bound_addr(), Some(neighbour_addr.as_str()));
+        sent.send(&Message::Leave { addr: other_addr, timestamp: 0 }).await.unwrap();
+        let message = accepted.recv().await.unwrap();
+        assert!(identity.verify(&message, &verifier).await.unwrap_err().contains("bound to"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Ed25519 node identities and gossip signatures.
+//!
+//! Every node keeps a signing key on disk, its public key is the node ID.
+//! The node ID is also written next to the key in a `.pub` file, so it can
+//! be copied into the trusted key directories of other nodes.
+//! A gossip message is signed by the node that originated it over its
+//! hybrid logical clock, origin address, origin node ID, sequence number and body, so
+//! forwarding nodes can change the sender, TTL, hop count and piggybacked
+//! membership updates but not the content.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH};
+use rand::rngs::OsRng;
+
+use crate::clock::HlcTimestamp;
+use crate::message::{Encoder, Message};
+
+// Domain separation prefix of the bytes signed for a gossip message.
+const GOSSIP_CONTEXT: &[u8] = b"gossip-v1";
+
+// Signing key of a node, its public half is the node ID.
+pub struct NodeKey {
+    signing_key: SigningKey,
+}
+
+impl NodeKey {
+    // Loads the key stored at `path`, generating and saving a new one if there is none.
+    // The node ID is written to the same path with a `.pub` extension.
+    pub fn load_or_generate(path: &Path) -> io::Result<Self> {
+        let key = NodeKey::load_or_generate_secret(path)?;
+        let public_path = path.with_extension("pub");
+        if !public_path.exists() {
+            fs::write(&public_path, format!("{}\n", node_id_hex(&key.node_id())))?;
+        }
+        Ok(key)
+    }
+
+    fn load_or_generate_secret(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let secret = decode_hex(contents.trim())
+                    .and_then(|bytes| <[u8; SECRET_KEY_LENGTH]>::try_from(bytes).ok())
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                        format!("{} does not hold a valid node key", path.display())))?;
+                Ok(NodeKey { signing_key: SigningKey::from_bytes(&secret) })
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let key = NodeKey { signing_key: SigningKey::generate(&mut OsRng) };
+                key.save(path)?;
+                Ok(key)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // Writes the secret key as hex, readable by the owner only.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        writeln!(file, "{}", encode_hex(self.signing_key.as_bytes()))?;
+        file.sync_all()
+    }
+
+    // Returns the node ID, the raw bytes of the public key.
+    pub fn node_id(&self) -> Vec<u8> {
+        self.signing_key.verifying_key().to_bytes().to_vec()
+    }
+
+    // Returns the X25519 private key matching this node's Ed25519 key, used by the Noise transport.
+    pub(crate) fn dh_private_key(&self) -> [u8; 32] {
+        self.signing_key.to_scalar_bytes()
+    }
+
+    // Builds a gossip message originated by this node and signs it, it may be forwarded `ttl` times.
+    pub fn sign_gossip(&self, hlc: HlcTimestamp, origin: String, seq: u64, body: String, ttl: u32) -> Message {
+        let origin_id = self.node_id();
+        let payload = gossip_payload(&hlc, &origin, &origin_id, seq, &body);
+        let signature = self.signing_key.sign(&payload).to_bytes().to_vec();
+        Message::Gossip { hlc, sender: origin.clone(), origin, origin_id, seq, body, signature, ttl, hops: 1, updates: Vec::new() }
+    }
+}
+
+// Checks that a gossip message was signed by the node ID it carries.
+pub fn verify_gossip(message: &Message) -> Result<(), String> {
+    let Message::Gossip { hlc, origin, origin_id, seq, body, signature, .. } = message else {
+        return Err("not a gossip message".to_string());
+    };
+    let key_bytes = <[u8; PUBLIC_KEY_LENGTH]>::try_from(origin_id.as_slice())
+        .map_err(|_| format!("node ID of {} is {} bytes long", origin, origin_id.len()))?;
+    let key = VerifyingKey::from_bytes(&key_bytes)
+        .map_err(|_| format!("node ID of {} is not a valid public key", origin))?;
+    let signature = Signature::from_slice(signature)
+        .map_err(|_| format!("malformed signature from {}", origin))?;
+    key.verify_strict(&gossip_payload(hlc, origin, origin_id, *seq, body), &signature)
+        .map_err(|_| format!("signature does not match {}", origin))
+}
+
+// Returns the X25519 public key matching a node ID, if it is a valid Ed25519 key.
+pub(crate) fn dh_public_key(node_id: &[u8]) -> Option<[u8; 32]> {
+    let key_bytes = <[u8; PUBLIC_KEY_LENGTH]>::try_from(node_id).ok()?;
+    let key = VerifyingKey::from_bytes(&key_bytes).ok()?;
+    Some(key.to_montgomery().to_bytes())
+}
+
+// Reads the node IDs of every `.pub` file in a directory.
+pub fn load_trusted_ids(dir: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut node_ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "pub") {
+            continue;
+        }
+        match load_node_id(&path) {
+            Ok(node_id) => node_ids.push(node_id),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => println!("{}", err),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(node_ids)
+}
+
+// Reads the node ID written in a single `.pub` file.
+pub fn load_node_id(path: &Path) -> io::Result<Vec<u8>> {
+    match decode_hex(fs::read_to_string(path)?.trim()) {
+        Some(node_id) if node_id.len() == PUBLIC_KEY_LENGTH => Ok(node_id),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid node ID in {}", path.display()))),
+    }
+}
+
+// Formats a node ID for display.
+pub fn node_id_hex(node_id: &[u8]) -> String {
+    encode_hex(node_id)
+}
+
+// Bytes covered by the signature of a gossip message.
+fn gossip_payload(hlc: &HlcTimestamp, origin: &str, origin_id: &[u8], seq: u64, body: &str) -> Vec<u8> {
+    let mut enc = Encoder::default();
+    enc.put_bytes(GOSSIP_CONTEXT);
+    enc.put_hlc(hlc);
+    enc.put_str(origin);
+    enc.put_bytes(origin_id);
+    enc.put_u64(seq);
+    enc.put_str(body);
+    enc.finish()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| text.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,15 @@
+//! Wire protocol shared by the Seed and Peer nodes.
+
+pub mod clock;
+pub mod frame;
+pub mod identity;
+pub mod keys;
+pub mod message;
+pub mod transport;
+
+pub use clock::{now_ms, HlcTimestamp, HybridClock};
+pub use frame::{read_frame, recv_message, send_message, write_frame};
+pub use identity::ConnectionIdentity;
+pub use keys::NodeKey;
+pub use message::{DecodeError, MemberRecord, MemberState, MembershipUpdate, Message, MessageId};
+pub use transport::{Connection, Transport, TransportMode};
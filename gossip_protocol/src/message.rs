@@ -0,0 +1,852 @@
+//! Typed messages exchanged between Seed and Peer nodes.
+//!
+//! A message is encoded as a tag byte followed by its fields. Strings and
+//! byte strings are written as a u32 big-endian length and the raw bytes,
+//! integers are written big-endian. Timestamps are u64 milliseconds since
+//! the UNIX epoch.
+
+use std::fmt;
+
+use crate::clock::HlcTimestamp;
+
+// Replicated state of one peer registration, exchanged between seeds.
+// The record with the newest `updated_at` wins, a removal wins a tie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberRecord {
+    pub addr: String,
+    pub updated_at: u64, // milliseconds since the UNIX epoch of the last change.
+    pub alive: bool,     // false once the peer has been removed.
+}
+
+// State of a peer in the membership view the peers gossip among themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberState {
+    Alive,   // joined the network, or refuted a suspicion about it.
+    Suspect, // stopped answering a neighbour, not confirmed dead yet.
+    Dead,    // confirmed dead by a neighbour.
+    Left,    // left the network on its own.
+}
+
+// Change to the membership view, piggybacked on gossip and liveness messages.
+// An update only replaces what a peer knows about `addr` if its incarnation is newer,
+// or equal and the state is worse. Only `addr` itself raises its incarnation, to refute a suspicion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MembershipUpdate {
+    pub addr: String,
+    pub state: MemberState,
+    pub incarnation: u64,
+}
+
+// Identity of a gossip message, unique as long as each origin never reuses a sequence number.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageId {
+    pub origin_id: Vec<u8>, // node ID of the peer that created the message.
+    pub seq: u64,           // sequence number of the message at its origin.
+}
+
+// Every request and reply understood by seeds and peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    // Peer asks a seed to register its listening address, presenting a join token if it has one.
+    JoinRequest { addr: String, timestamp: u64, token: Option<String> },
+    // Peer asks a seed for the other registered peers, optionally one page at a time.
+    GetConnectedNodes { addr: String, timestamp: u64, offset: u32, limit: Option<u32> },
+    // One page of the registered peers, `total` counts every peer the seed can return.
+    ConnectedNodes { total: u32, offset: u32, nodes: Vec<String> },
+    // Peer reports a neighbour that stopped answering liveness requests.
+    DeadNode { dead_node: String, timestamp: u64, reporter: String },
+    // Liveness probe, the reply is sent to `addr`.
+    LivenessRequest { timestamp: u64, addr: String, updates: Vec<MembershipUpdate> },
+    // Answer to a liveness probe sent by `requester`.
+    LivenessReply { timestamp: u64, requester: String, addr: String, updates: Vec<MembershipUpdate> },
+    // Gossip message created and signed by `origin`, forwarded by `sender`.
+    Gossip {
+        hlc: HlcTimestamp,  // hybrid logical clock of `origin` when it created the message.
+        origin: String,     // listening address of the node that created the message.
+        origin_id: Vec<u8>, // node ID of `origin`, its Ed25519 public key.
+        seq: u64,           // sequence number of the message at `origin`.
+        body: String,
+        signature: Vec<u8>, // signature by `origin_id`, see `keys::verify_gossip`.
+        sender: String,     // node that forwarded this copy, not covered by the signature.
+        ttl: u32,           // forwards left, decremented by every forwarding node.
+        hops: u32,          // links this copy has crossed, incremented by every forwarding node.
+        updates: Vec<MembershipUpdate>, // membership updates piggybacked by `sender`, not covered by the signature.
+    },
+    // Free-form reply from a seed.
+    Response { text: String },
+    // Peer announces it is leaving the network.
+    Leave { addr: String, timestamp: u64 },
+    // Peer asks another peer to become neighbours.
+    Connect { addr: String, timestamp: u64 },
+    // The link requested by `Connect` was agreed by `addr`.
+    Accept { addr: String },
+    // The link requested by `Connect`, or the join requested by `JoinRequest`, was refused by `addr`.
+    Reject { addr: String, reason: String },
+    // Anti-entropy exchange between seeds carrying the sender's full membership.
+    SeedSync { sender: String, members: Vec<MemberRecord> },
+    // Anti-entropy digest of the gossip messages `sender` received recently.
+    Digest { sender: String, ids: Vec<MessageId> },
+    // Asks for the gossip messages `sender` is missing.
+    Pull { sender: String, ids: Vec<MessageId> },
+    // Plumtree lazy push, announces gossip messages `sender` received without sending them.
+    IHave { sender: String, ids: Vec<MessageId> },
+    // Plumtree tree repair, asks for announced messages and makes the link eager again.
+    Graft { sender: String, ids: Vec<MessageId> },
+    // Plumtree tree pruning, asks the receiver to only announce messages on this link.
+    Prune { sender: String },
+    // Asks a neighbour to probe `target`, which stopped answering `sender` directly.
+    PingReq { sender: String, target: String },
+    // Tells the requester of a PING_REQ that `target` answered the probe `sender` sent it.
+    IndirectAck { sender: String, target: String },
+    // Asks the node listening at an address whether it opened the connection from socket `from` to socket `to`.
+    DialCheck { from: String, to: String },
+    // Answer to a DIAL_CHECK, `dialed` is true if the node opened that connection.
+    DialCheckReply { from: String, to: String, dialed: bool },
+}
+
+// Reasons a byte buffer could not be decoded into a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEof,
+    UnknownTag(u8),
+    InvalidUtf8,
+    InvalidMemberState(u8),
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "message ended unexpectedly"),
+            DecodeError::UnknownTag(tag) => write!(f, "unknown message tag {}", tag),
+            DecodeError::InvalidUtf8 => write!(f, "message field is not valid UTF-8"),
+            DecodeError::InvalidMemberState(state) => write!(f, "unknown member state {}", state),
+            DecodeError::TrailingBytes(n) => write!(f, "{} unexpected bytes after message", n),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// Tags identifying each message variant on the wire.
+const TAG_JOIN_REQUEST: u8 = 1;
+const TAG_GET_CONNECTED_NODES: u8 = 2;
+const TAG_DEAD_NODE: u8 = 3;
+const TAG_LIVENESS_REQUEST: u8 = 4;
+const TAG_LIVENESS_REPLY: u8 = 5;
+const TAG_GOSSIP: u8 = 6;
+const TAG_RESPONSE: u8 = 7;
+const TAG_CONNECTED_NODES: u8 = 8;
+const TAG_SEED_SYNC: u8 = 9;
+const TAG_LEAVE: u8 = 10;
+const TAG_CONNECT: u8 = 11;
+const TAG_ACCEPT: u8 = 12;
+const TAG_REJECT: u8 = 13;
+const TAG_DIGEST: u8 = 14;
+const TAG_PULL: u8 = 15;
+const TAG_IHAVE: u8 = 16;
+const TAG_GRAFT: u8 = 17;
+const TAG_PRUNE: u8 = 18;
+const TAG_PING_REQ: u8 = 19;
+const TAG_INDIRECT_ACK: u8 = 20;
+const TAG_DIAL_CHECK: u8 = 21;
+const TAG_DIAL_CHECK_REPLY: u8 = 22;
+
+impl Message {
+    // Returns the listening address the sender of this message claims to have.
+    pub fn claimed_sender(&self) -> Option<&str> {
+        match self {
+            Message::JoinRequest { addr, .. }
+            | Message::GetConnectedNodes { addr, .. }
+            | Message::LivenessRequest { addr, .. }
+            | Message::LivenessReply { addr, .. }
+            | Message::Leave { addr, .. }
+            | Message::Connect { addr, .. } => Some(addr),
+            Message::DeadNode { reporter, .. } => Some(reporter),
+            Message::Gossip { sender, .. }
+            | Message::SeedSync { sender, .. }
+            | Message::Digest { sender, .. }
+            | Message::Pull { sender, .. }
+            | Message::IHave { sender, .. }
+            | Message::Graft { sender, .. }
+            | Message::Prune { sender }
+            | Message::PingReq { sender, .. }
+            | Message::IndirectAck { sender, .. } => Some(sender),
+            Message::ConnectedNodes { .. }
+            | Message::Response { .. }
+            | Message::Accept { .. }
+            | Message::Reject { .. }
+            | Message::DialCheck { .. }
+            | Message::DialCheckReply { .. } => None,
+        }
+    }
+
+    // Returns the identity of a gossip message.
+    pub fn gossip_id(&self) -> Option<MessageId> {
+        match self {
+            Message::Gossip { origin_id, seq, .. } => Some(MessageId { origin_id: origin_id.clone(), seq: *seq }),
+            _ => None,
+        }
+    }
+
+    // Returns the membership updates piggybacked on this message.
+    pub fn membership_updates(&self) -> &[MembershipUpdate] {
+        match self {
+            Message::LivenessRequest { updates, .. }
+            | Message::LivenessReply { updates, .. }
+            | Message::Gossip { updates, .. } => updates,
+            _ => &[],
+        }
+    }
+
+    // Returns the message carrying `piggyback` as its membership updates, unchanged if it can't carry any.
+    pub fn with_updates(mut self, piggyback: Vec<MembershipUpdate>) -> Message {
+        match &mut self {
+            Message::LivenessRequest { updates, .. }
+            | Message::LivenessReply { updates, .. }
+            | Message::Gossip { updates, .. } => *updates = piggyback,
+            _ => {}
+        }
+        self
+    }
+
+    // Returns the copy of a gossip message sent on by `forwarder`, None once it has no forwards left.
+    // The updates piggybacked by the previous sender are dropped.
+    pub fn forwarded_by(&self, forwarder: &str) -> Option<Message> {
+        let mut message = self.clone();
+        match &mut message {
+            Message::Gossip { sender, ttl, hops, updates, .. } if *ttl > 0 => {
+                *sender = forwarder.to_string();
+                *ttl -= 1;
+                *hops += 1;
+                updates.clear();
+                Some(message)
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the copy of a gossip message `resender` sends to a peer that missed it.
+    // The copy has no forwards left, so it repairs that peer without being flooded again.
+    pub fn resent_by(&self, resender: &str) -> Message {
+        let mut message = self.clone();
+        if let Message::Gossip { sender, ttl, hops, updates, .. } = &mut message {
+            *sender = resender.to_string();
+            *ttl = 0;
+            *hops += 1;
+            updates.clear();
+        }
+        message
+    }
+
+    // Encodes the message into bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        match self {
+            Message::JoinRequest { addr, timestamp, token } => {
+                enc.put_u8(TAG_JOIN_REQUEST);
+                enc.put_str(addr);
+                enc.put_u64(*timestamp);
+                enc.put_opt_str(token.as_deref());
+            }
+            Message::GetConnectedNodes { addr, timestamp, offset, limit } => {
+                enc.put_u8(TAG_GET_CONNECTED_NODES);
+                enc.put_str(addr);
+                enc.put_u64(*timestamp);
+                enc.put_u32(*offset);
+                enc.put_opt_u32(*limit);
+            }
+            Message::ConnectedNodes { total, offset, nodes } => {
+                enc.put_u8(TAG_CONNECTED_NODES);
+                enc.put_u32(*total);
+                enc.put_u32(*offset);
+                enc.put_str_list(nodes);
+            }
+            Message::DeadNode { dead_node, timestamp, reporter } => {
+                enc.put_u8(TAG_DEAD_NODE);
+                enc.put_str(dead_node);
+                enc.put_u64(*timestamp);
+                enc.put_str(reporter);
+            }
+            Message::LivenessRequest { timestamp, addr, updates } => {
+                enc.put_u8(TAG_LIVENESS_REQUEST);
+                enc.put_u64(*timestamp);
+                enc.put_str(addr);
+                enc.put_update_list(updates);
+            }
+            Message::LivenessReply { timestamp, requester, addr, updates } => {
+                enc.put_u8(TAG_LIVENESS_REPLY);
+                enc.put_u64(*timestamp);
+                enc.put_str(requester);
+                enc.put_str(addr);
+                enc.put_update_list(updates);
+            }
+            Message::Gossip { hlc, origin, origin_id, seq, body, signature, sender, ttl, hops, updates } => {
+                enc.put_u8(TAG_GOSSIP);
+                enc.put_hlc(hlc);
+                enc.put_str(origin);
+                enc.put_bytes(origin_id);
+                enc.put_u64(*seq);
+                enc.put_str(body);
+                enc.put_bytes(signature);
+                enc.put_str(sender);
+                enc.put_u32(*ttl);
+                enc.put_u32(*hops);
+                enc.put_update_list(updates);
+            }
+            Message::Response { text } => {
+                enc.put_u8(TAG_RESPONSE);
+                enc.put_str(text);
+            }
+            Message::Leave { addr, timestamp } => {
+                enc.put_u8(TAG_LEAVE);
+                enc.put_str(addr);
+                enc.put_u64(*timestamp);
+            }
+            Message::Connect { addr, timestamp } => {
+                enc.put_u8(TAG_CONNECT);
+                enc.put_str(addr);
+                enc.put_u64(*timestamp);
+            }
+            Message::Accept { addr } => {
+                enc.put_u8(TAG_ACCEPT);
+                enc.put_str(addr);
+            }
+            Message::Reject { addr, reason } => {
+                enc.put_u8(TAG_REJECT);
+                enc.put_str(addr);
+                enc.put_str(reason);
+            }
+            Message::SeedSync { sender, members } => {
+                enc.put_u8(TAG_SEED_SYNC);
+                enc.put_str(sender);
+                enc.put_u32(members.len() as u32);
+                for member in members {
+                    enc.put_str(&member.addr);
+                    enc.put_u64(member.updated_at);
+                    enc.put_bool(member.alive);
+                }
+            }
+            Message::Digest { sender, ids } => {
+                enc.put_u8(TAG_DIGEST);
+                enc.put_str(sender);
+                enc.put_id_list(ids);
+            }
+            Message::Pull { sender, ids } => {
+                enc.put_u8(TAG_PULL);
+                enc.put_str(sender);
+                enc.put_id_list(ids);
+            }
+            Message::IHave { sender, ids } => {
+                enc.put_u8(TAG_IHAVE);
+                enc.put_str(sender);
+                enc.put_id_list(ids);
+            }
+            Message::Graft { sender, ids } => {
+                enc.put_u8(TAG_GRAFT);
+                enc.put_str(sender);
+                enc.put_id_list(ids);
+            }
+            Message::Prune { sender } => {
+                enc.put_u8(TAG_PRUNE);
+                enc.put_str(sender);
+            }
+            Message::PingReq { sender, target } => {
+                enc.put_u8(TAG_PING_REQ);
+                enc.put_str(sender);
+                enc.put_str(target);
+            }
+            Message::IndirectAck { sender, target } => {
+                enc.put_u8(TAG_INDIRECT_ACK);
+                enc.put_str(sender);
+                enc.put_str(target);
+            }
+            Message::DialCheck { from, to } => {
+                enc.put_u8(TAG_DIAL_CHECK);
+                enc.put_str(from);
+                enc.put_str(to);
+            }
+            Message::DialCheckReply { from, to, dialed } => {
+                enc.put_u8(TAG_DIAL_CHECK_REPLY);
+                enc.put_str(from);
+                enc.put_str(to);
+                enc.put_bool(*dialed);
+            }
+        }
+        enc.finish()
+    }
+
+    // Decodes a message from bytes, the whole buffer must be consumed.
+    pub fn decode(bytes: &[u8]) -> Result<Message, DecodeError> {
+        let mut dec = Decoder::new(bytes);
+        let message = match dec.get_u8()? {
+            TAG_JOIN_REQUEST => Message::JoinRequest {
+                addr: dec.get_str()?,
+                timestamp: dec.get_u64()?,
+                token: dec.get_opt_str()?,
+            },
+            TAG_GET_CONNECTED_NODES => Message::GetConnectedNodes {
+                addr: dec.get_str()?,
+                timestamp: dec.get_u64()?,
+                offset: dec.get_u32()?,
+                limit: dec.get_opt_u32()?,
+            },
+            TAG_CONNECTED_NODES => Message::ConnectedNodes {
+                total: dec.get_u32()?,
+                offset: dec.get_u32()?,
+                nodes: dec.get_str_list()?,
+            },
+            TAG_DEAD_NODE => Message::DeadNode {
+                dead_node: dec.get_str()?,
+                timestamp: dec.get_u64()?,
+                reporter: dec.get_str()?,
+            },
+            TAG_LIVENESS_REQUEST => Message::LivenessRequest {
+                timestamp: dec.get_u64()?,
+                addr: dec.get_str()?,
+                updates: dec.get_update_list()?,
+            },
+            TAG_LIVENESS_REPLY => Message::LivenessReply {
+                timestamp: dec.get_u64()?,
+                requester: dec.get_str()?,
+                addr: dec.get_str()?,
+                updates: dec.get_update_list()?,
+            },
+            TAG_GOSSIP => Message::Gossip {
+                hlc: dec.get_hlc()?,
+                origin: dec.get_str()?,
+                origin_id: dec.get_bytes()?,
+                seq: dec.get_u64()?,
+                body: dec.get_str()?,
+                signature: dec.get_bytes()?,
+                sender: dec.get_str()?,
+                ttl: dec.get_u32()?,
+                hops: dec.get_u32()?,
+                updates: dec.get_update_list()?,
+            },
+            TAG_RESPONSE => Message::Response { text: dec.get_str()? },
+            TAG_LEAVE => Message::Leave {
+                addr: dec.get_str()?,
+                timestamp: dec.get_u64()?,
+            },
+            TAG_CONNECT => Message::Connect {
+                addr: dec.get_str()?,
+                timestamp: dec.get_u64()?,
+            },
+            TAG_ACCEPT => Message::Accept { addr: dec.get_str()? },
+            TAG_REJECT => Message::Reject {
+                addr: dec.get_str()?,
+                reason: dec.get_str()?,
+            },
+            TAG_SEED_SYNC => {
+                let sender = dec.get_str()?;
+                let count = dec.get_u32()? as usize;
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    members.push(MemberRecord {
+                        addr: dec.get_str()?,
+                        updated_at: dec.get_u64()?,
+                        alive: dec.get_bool()?,
+                    });
+                }
+                Message::SeedSync { sender, members }
+            }
+            TAG_DIGEST => Message::Digest {
+                sender: dec.get_str()?,
+                ids: dec.get_id_list()?,
+            },
+            TAG_PULL => Message::Pull {
+                sender: dec.get_str()?,
+                ids: dec.get_id_list()?,
+            },
+            TAG_IHAVE => Message::IHave {
+                sender: dec.get_str()?,
+                ids: dec.get_id_list()?,
+            },
+            TAG_GRAFT => Message::Graft {
+                sender: dec.get_str()?,
+                ids: dec.get_id_list()?,
+            },
+            TAG_PRUNE => Message::Prune { sender: dec.get_str()? },
+            TAG_PING_REQ => Message::PingReq {
+                sender: dec.get_str()?,
+                target: dec.get_str()?,
+            },
+            TAG_INDIRECT_ACK => Message::IndirectAck {
+                sender: dec.get_str()?,
+                target: dec.get_str()?,
+            },
+            TAG_DIAL_CHECK => Message::DialCheck {
+                from: dec.get_str()?,
+                to: dec.get_str()?,
+            },
+            TAG_DIAL_CHECK_REPLY => Message::DialCheckReply {
+                from: dec.get_str()?,
+                to: dec.get_str()?,
+                dialed: dec.get_bool()?,
+            },
+            tag => return Err(DecodeError::UnknownTag(tag)),
+        };
+        dec.finish()?;
+        Ok(message)
+    }
+}
+
+// Appends fields to an output buffer.
+#[derive(Default)]
+pub(crate) struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn put_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub(crate) fn put_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn put_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn put_bool(&mut self, value: bool) {
+        self.put_u8(value as u8);
+    }
+
+    pub(crate) fn put_opt_u32(&mut self, value: Option<u32>) {
+        match value {
+            Some(value) => {
+                self.put_u8(1);
+                self.put_u32(value);
+            }
+            None => self.put_u8(0),
+        }
+    }
+
+    pub(crate) fn put_bytes(&mut self, value: &[u8]) {
+        self.put_u32(value.len() as u32);
+        self.buffer.extend_from_slice(value);
+    }
+
+    pub(crate) fn put_str(&mut self, value: &str) {
+        self.put_bytes(value.as_bytes());
+    }
+
+    pub(crate) fn put_opt_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.put_u8(1);
+                self.put_str(value);
+            }
+            None => self.put_u8(0),
+        }
+    }
+
+    pub(crate) fn put_str_list(&mut self, values: &[String]) {
+        self.put_u32(values.len() as u32);
+        for value in values {
+            self.put_str(value);
+        }
+    }
+
+    pub(crate) fn put_id_list(&mut self, ids: &[MessageId]) {
+        self.put_u32(ids.len() as u32);
+        for id in ids {
+            self.put_bytes(&id.origin_id);
+            self.put_u64(id.seq);
+        }
+    }
+
+    pub(crate) fn put_hlc(&mut self, hlc: &HlcTimestamp) {
+        self.put_u64(hlc.wall_ms);
+        self.put_u32(hlc.logical);
+    }
+
+    pub(crate) fn put_update_list(&mut self, updates: &[MembershipUpdate]) {
+        self.put_u32(updates.len() as u32);
+        for update in updates {
+            self.put_str(&update.addr);
+            self.put_u8(match update.state {
+                MemberState::Alive => 0,
+                MemberState::Suspect => 1,
+                MemberState::Dead => 2,
+                MemberState::Left => 3,
+            });
+            self.put_u64(update.incarnation);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+// Reads fields back from an input buffer.
+pub(crate) struct Decoder<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(buffer: &'a [u8]) -> Self {
+        Decoder { buffer }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buffer.len() < n {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (head, tail) = self.buffer.split_at(n);
+        self.buffer = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn get_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn get_u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub(crate) fn get_u64(&mut self) -> Result<u64, DecodeError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub(crate) fn get_bool(&mut self) -> Result<bool, DecodeError> {
+        Ok(self.get_u8()? != 0)
+    }
+
+    pub(crate) fn get_opt_u32(&mut self) -> Result<Option<u32>, DecodeError> {
+        match self.get_u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.get_u32()?)),
+        }
+    }
+
+    pub(crate) fn get_bytes(&mut self) -> Result<Vec<u8>, DecodeError> {
+        let len = self.get_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub(crate) fn get_str(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.get_bytes()?).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub(crate) fn get_opt_str(&mut self) -> Result<Option<String>, DecodeError> {
+        match self.get_u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.get_str()?)),
+        }
+    }
+
+    pub(crate) fn get_str_list(&mut self) -> Result<Vec<String>, DecodeError> {
+        let count = self.get_u32()? as usize;
+        // Every entry takes at least its length prefix, so cap the allocation by what is left.
+        let mut values = Vec::with_capacity(count.min(self.buffer.len() / 4));
+        for _ in 0..count {
+            values.push(self.get_str()?);
+        }
+        Ok(values)
+    }
+
+    pub(crate) fn get_id_list(&mut self) -> Result<Vec<MessageId>, DecodeError> {
+        let count = self.get_u32()? as usize;
+        // Every entry takes at least its length prefix and sequence number.
+        let mut ids = Vec::with_capacity(count.min(self.buffer.len() / 12));
+        for _ in 0..count {
+            ids.push(MessageId { origin_id: self.get_bytes()?, seq: self.get_u64()? });
+        }
+        Ok(ids)
+    }
+
+    pub(crate) fn get_hlc(&mut self) -> Result<HlcTimestamp, DecodeError> {
+        Ok(HlcTimestamp { wall_ms: self.get_u64()?, logical: self.get_u32()? })
+    }
+
+    pub(crate) fn get_update_list(&mut self) -> Result<Vec<MembershipUpdate>, DecodeError> {
+        let count = self.get_u32()? as usize;
+        // Every entry takes at least its address length, state and incarnation.
+        let mut updates = Vec::with_capacity(count.min(self.buffer.len() / 13));
+        for _ in 0..count {
+            let addr = self.get_str()?;
+            let state = match self.get_u8()? {
+                0 => MemberState::Alive,
+                1 => MemberState::Suspect,
+                2 => MemberState::Dead,
+                3 => MemberState::Left,
+                state => return Err(DecodeError::InvalidMemberState(state)),
+            };
+            updates.push(MembershipUpdate { addr, state, incarnation: self.get_u64()? });
+        }
+        Ok(updates)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), DecodeError> {
+        if self.buffer.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes(self.buffer.len()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    fn text() -> impl Strategy<Value = String> {
+        "\\PC{0,24}"
+    }
+
+    fn bytes() -> impl Strategy<Value = Vec<u8>> {
+        vec(any::<u8>(), 0..64)
+    }
+
+    fn hlc() -> impl Strategy<Value = HlcTimestamp> {
+        (any::<u64>(), any::<u32>()).prop_map(|(wall_ms, logical)| HlcTimestamp { wall_ms, logical })
+    }
+
+    fn ids() -> impl Strategy<Value = Vec<MessageId>> {
+        vec((bytes(), any::<u64>()).prop_map(|(origin_id, seq)| MessageId { origin_id, seq }), 0..8)
+    }
+
+    fn updates() -> impl Strategy<Value = Vec<MembershipUpdate>> {
+        let state = prop_oneof![
+            Just(MemberState::Alive),
+            Just(MemberState::Suspect),
+            Just(MemberState::Dead),
+            Just(MemberState::Left),
+        ];
+        vec((text(), state, any::<u64>())
+            .prop_map(|(addr, state, incarnation)| MembershipUpdate { addr, state, incarnation }), 0..8)
+    }
+
+    fn members() -> impl Strategy<Value = Vec<MemberRecord>> {
+        vec((text(), any::<u64>(), any::<bool>())
+            .prop_map(|(addr, updated_at, alive)| MemberRecord { addr, updated_at, alive }), 0..8)
+    }
+
+    // One strategy per variant, so every tag is generated.
+    fn message() -> impl Strategy<Value = Message> {
+        prop_oneof![
+            (text(), any::<u64>(), proptest::option::of(text()))
+                .prop_map(|(addr, timestamp, token)| Message::JoinRequest { addr, timestamp, token }),
+            (text(), any::<u64>(), any::<u32>(), any::<Option<u32>>())
+                .prop_map(|(addr, timestamp, offset, limit)| Message::GetConnectedNodes { addr, timestamp, offset, limit }),
+            (any::<u32>(), any::<u32>(), vec(text(), 0..8))
+                .prop_map(|(total, offset, nodes)| Message::ConnectedNodes { total, offset, nodes }),
+            (text(), any::<u64>(), text())
+                .prop_map(|(dead_node, timestamp, reporter)| Message::DeadNode { dead_node, timestamp, reporter }),
+            (any::<u64>(), text(), updates())
+                .prop_map(|(timestamp, addr, updates)| Message::LivenessRequest { timestamp, addr, updates }),
+            (any::<u64>(), text(), text(), updates())
+                .prop_map(|(timestamp, requester, addr, updates)| Message::LivenessReply { timestamp, requester, addr, updates }),
+            (hlc(), text(), bytes(), any::<u64>(), text(), bytes(), text(), any::<u32>(), any::<u32>(), updates())
+                .prop_map(|(hlc, origin, origin_id, seq, body, signature, sender, ttl, hops, updates)| {
+                    Message::Gossip { hlc, origin, origin_id, seq, body, signature, sender, ttl, hops, updates }
+                }),
+            text().prop_map(|text| Message::Response { text }),
+            (text(), any::<u64>()).prop_map(|(addr, timestamp)| Message::Leave { addr, timestamp }),
+            (text(), any::<u64>()).prop_map(|(addr, timestamp)| Message::Connect { addr, timestamp }),
+            text().prop_map(|addr| Message::Accept { addr }),
+            (text(), text()).prop_map(|(addr, reason)| Message::Reject { addr, reason }),
+            (text(), members()).prop_map(|(sender, members)| Message::SeedSync { sender, members }),
+            (text(), ids()).prop_map(|(sender, ids)| Message::Digest { sender, ids }),
+            (text(), ids()).prop_map(|(sender, ids)| Message::Pull { sender, ids }),
+            (text(), ids()).prop_map(|(sender, ids)| Message::IHave { sender, ids }),
+            (text(), ids()).prop_map(|(sender, ids)| Message::Graft { sender, ids }),
+            text().prop_map(|sender| Message::Prune { sender }),
+            (text(), text()).prop_map(|(sender, target)| Message::PingReq { sender, target }),
+            (text(), text()).prop_map(|(sender, target)| Message::IndirectAck { sender, target }),
+            (text(), text()).prop_map(|(from, to)| Message::DialCheck { from, to }),
+            (text(), text(), any::<bool>()).prop_map(|(from, to, dialed)| Message::DialCheckReply { from, to, dialed }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn every_variant_round_trips(message in message()) {
+            prop_assert_eq!(Message::decode(&message.encode()), Ok(message));
+        }
+
+        #[test]
+        fn truncated_messages_are_rejected(message in message(), cut in any::<prop::sample::Index>()) {
+            let bytes = message.encode();
+            let len = cut.index(bytes.len());
+            prop_assert_eq!(Message::decode(&bytes[..len]), Err(DecodeError::UnexpectedEof));
+        }
+
+        #[test]
+        fn trailing_bytes_are_rejected(message in message(), extra in vec(any::<u8>(), 1..8)) {
+            let mut bytes = message.encode();
+            bytes.extend_from_slice(&extra);
+            prop_assert_eq!(Message::decode(&bytes), Err(DecodeError::TrailingBytes(extra.len())));
+        }
+    }
+
+    #[test]
+    fn empty_buffer_is_rejected() {
+        assert_eq!(Message::decode(&[]), Err(DecodeError::UnexpectedEof));
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected() {
+        for tag in [0, TAG_DIAL_CHECK_REPLY + 1, u8::MAX] {
+            assert_eq!(Message::decode(&[tag]), Err(DecodeError::UnknownTag(tag)));
+        }
+    }
+
+    #[test]
+    fn invalid_member_state_is_rejected() {
+        let message = Message::LivenessRequest {
+            timestamp: 1,
+            addr: "127.0.0.1:9090".to_string(),
+            updates: vec![MembershipUpdate { addr: "127.0.0.1:9091".to_string(), state: MemberState::Left, incarnation: 2 }],
+        };
+        let mut bytes = message.encode();
+        // the state byte sits right before the incarnation that ends the message.
+        let state_at = bytes.len() - 9;
+        bytes[state_at] = 4;
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::InvalidMemberState(4)));
+    }
+
+    #[test]
+    fn invalid_utf8_is_rejected() {
+        let mut bytes = Message::Response { text: "ok".to_string() }.encode();
+        let last = bytes.len() - 1;
+        bytes[last] = 0xff;
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::InvalidUtf8));
+    }
+
+    // A list count far larger than the buffer must fail cleanly without allocating for it.
+    fn oversized_list(tag: u8, before: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![tag];
+        bytes.extend_from_slice(before);
+        bytes.extend_from_slice(&u32::MAX.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn oversized_str_list_is_rejected() {
+        let bytes = oversized_list(TAG_CONNECTED_NODES, &[0; 8]);
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::UnexpectedEof));
+    }
+
+    #[test]
+    fn oversized_id_list_is_rejected() {
+        let bytes = oversized_list(TAG_DIGEST, &[0; 4]);
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::UnexpectedEof));
+    }
+
+    #[test]
+    fn oversized_update_list_is_rejected() {
+        let bytes = oversized_list(TAG_LIVENESS_REQUEST, &[0; 12]);
+        assert_eq!(Message::decode(&bytes), Err(DecodeError::UnexpectedEof));
+    }
+}
@@ -0,0 +1,393 @@
+//! Connections between Seed and Peer nodes, in plaintext or encrypted with Noise.
+//!
+//! The Noise transport starts every connection with a `Noise_XX` handshake
+//! using the X25519 keys matching both nodes' Ed25519 keys, so each end
+//! learns the other's node ID and closes the connection unless it is
+//! trusted. Every frame is then encrypted and sent as one or more Noise
+//! messages, each prefixed with its u16 big-endian length.
+//!
+//! Every transport also remembers the connections it opened, so a node
+//! receiving one can connect back and ask whether it really came from the
+//! address it claims, see `ConnectionIdentity`.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use snow::{Builder, HandshakeState, TransportState};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::frame::{recv_message, send_message, MAX_FRAME_LEN};
+use crate::keys::{self, NodeKey};
+use crate::message::Message;
+
+// Noise protocol used by every encrypted connection.
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+// Largest Noise message, ciphertext and tag included.
+const MAX_NOISE_LEN: usize = 65535;
+// Authentication tag appended to every encrypted Noise message.
+const TAG_LEN: usize = 16;
+// How long the handshake of a new connection may take.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a closed connection can still be confirmed as opened by this node,
+// so a message read after its sender hung up can still be checked.
+const DIALED_GRACE: Duration = Duration::from_secs(30);
+
+// Connections opened by a node by local and remote socket address, and when each was closed.
+type DialedSockets = Arc<Mutex<HashMap<(SocketAddr, SocketAddr), Option<Instant>>>>;
+
+// Transports a node can be configured with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportMode {
+    Plain, // unencrypted TCP.
+    Noise, // Noise_XX with mutual authentication against trusted node IDs.
+}
+
+impl FromStr for TransportMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "plain" => Ok(TransportMode::Plain),
+            "noise" => Ok(TransportMode::Noise),
+            _ => Err(format!("unknown transport {}", value)),
+        }
+    }
+}
+
+// Opens and accepts connections for one node.
+pub struct Transport {
+    noise: Option<NoiseKeys>, // keys used to encrypt connections, plaintext when unset.
+    dialed: DialedSockets, // connections this node opened, open or recently closed.
+}
+
+// Local key and trusted node IDs of the Noise transport.
+struct NoiseKeys {
+    private_key: [u8; 32], // X25519 private key matching the node key.
+    trusted_dirs: Vec<PathBuf>, // directories whose `.pub` files hold trusted node IDs.
+    trusted: Mutex<HashMap<[u8; 32], Vec<u8>>>, // X25519 public key -> trusted node ID.
+}
+
+impl Transport {
+    // Transport sending frames over plain TCP.
+    pub fn plain() -> Self {
+        Transport { noise: None, dialed: DialedSockets::default() }
+    }
+
+    // Transport encrypting every connection and only talking to the node IDs found in `trusted_dirs`.
+    pub fn noise(key: &NodeKey, trusted_dirs: Vec<PathBuf>) -> Self {
+        let noise = NoiseKeys {
+            private_key: key.dh_private_key(),
+            trusted_dirs,
+            trusted: Mutex::new(HashMap::new()),
+        };
+        noise.reload_trusted();
+        Transport { noise: Some(noise), dialed: DialedSockets::default() }
+    }
+
+    // Connects to a node and completes the handshake.
+    pub async fn connect(&self, addr: &str) -> io::Result<Connection> {
+        let stream = TcpStream::connect(addr).await?;
+        let mut conn = self.handshake(stream, true).await?;
+        // remembered so the node reached can check this node opened the connection.
+        let mut dialed = self.dialed.lock().unwrap();
+        dialed.retain(|_, closed_at| closed_at.is_none_or(|closed_at| closed_at.elapsed() < DIALED_GRACE));
+        dialed.insert((conn.local_addr, conn.peer_addr), None);
+        conn.dialed = Some(self.dialed.clone());
+        Ok(conn)
+    }
+
+    // Completes the handshake of a connection returned by `accept`.
+    pub async fn accept(&self, stream: TcpStream) -> io::Result<Connection> {
+        self.handshake(stream, false).await
+    }
+
+    // Checks whether this node opened the connection from `local` to `remote`, still open or closed recently.
+    pub fn dialed(&self, local: SocketAddr, remote: SocketAddr) -> bool {
+        self.dialed.lock().unwrap().get(&(local, remote))
+            .is_some_and(|closed_at| closed_at.is_none_or(|closed_at| closed_at.elapsed() < DIALED_GRACE))
+    }
+
+    // Returns the answer to a DIAL_CHECK, None for any other message.
+    pub fn dial_check_reply(&self, message: &Message) -> Option<Message> {
+        let Message::DialCheck { from, to } = message else {
+            return None;
+        };
+        let dialed = match (from.parse(), to.parse()) {
+            (Ok(local), Ok(remote)) => self.dialed(local, remote),
+            _ => false,
+        };
+        Some(Message::DialCheckReply { from: from.clone(), to: to.clone(), dialed })
+    }
+
+    async fn handshake(&self, mut stream: TcpStream, initiator: bool) -> io::Result<Connection> {
+        let (local_addr, peer_addr) = (stream.local_addr()?, stream.peer_addr()?);
+        let Some(noise) = &self.noise else {
+            return Ok(Connection { stream, local_addr, peer_addr, noise: None, remote_id: None, dialed: None });
+        };
+        let builder = Builder::new(NOISE_PARAMS.parse().map_err(noise_error)?)
+            .local_private_key(&noise.private_key);
+        let state = if initiator { builder.build_initiator() } else { builder.build_responder() }
+            .map_err(noise_error)?;
+
+        let (state, remote_id) = timeout(HANDSHAKE_TIMEOUT, noise.run_handshake(&mut stream, state))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))??;
+        Ok(Connection { stream, local_addr, peer_addr, noise: Some(state), remote_id: Some(remote_id), dialed: None })
+    }
+}
+
+impl NoiseKeys {
+    // Exchanges the three XX messages and checks the remote static key, returns its node ID.
+    async fn run_handshake(&self, stream: &mut TcpStream, mut state: HandshakeState)
+        -> io::Result<(TransportState, Vec<u8>)> {
+        let mut buffer = vec![0u8; MAX_NOISE_LEN];
+        let mut remote_id = None;
+        // -> e, <- e ee s es, -> s se
+        for turn in 0..3 {
+            if (turn % 2 == 0) == state.is_initiator() {
+                let len = state.write_message(&[], &mut buffer).map_err(noise_error)?;
+                write_noise_message(stream, &buffer[..len]).await?;
+            } else {
+                let message = read_noise_message(stream).await?;
+                state.read_message(&message, &mut buffer).map_err(noise_error)?;
+                // the remote key is checked as soon as it arrives, before revealing anything more.
+                if let (None, Some(remote_key)) = (&remote_id, state.get_remote_static()) {
+                    remote_id = Some(self.check_trusted(remote_key)?);
+                }
+            }
+        }
+        let remote_id = remote_id
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "handshake did not reveal the remote key"))?;
+        Ok((state.into_transport_mode().map_err(noise_error)?, remote_id))
+    }
+
+    // Returns the node ID of a trusted X25519 key, re-reading the trusted directories once if it is unknown.
+    fn check_trusted(&self, remote_key: &[u8]) -> io::Result<Vec<u8>> {
+        let remote_key = <[u8; 32]>::try_from(remote_key)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid remote key"))?;
+        if let Some(node_id) = self.trusted.lock().unwrap().get(&remote_key) {
+            return Ok(node_id.clone());
+        }
+        // nodes enrolled after this one started are picked up without a restart.
+        self.reload_trusted();
+        self.trusted.lock().unwrap().get(&remote_key).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::PermissionDenied,
+                format!("untrusted static key {}", keys::node_id_hex(&remote_key)))
+        })
+    }
+
+    // Reads the node IDs of every trusted directory.
+    fn reload_trusted(&self) {
+        let mut trusted = HashMap::new();
+        for dir in &self.trusted_dirs {
+            match keys::load_trusted_ids(dir) {
+                Ok(node_ids) => {
+                    for node_id in node_ids {
+                        if let Some(dh_key) = keys::dh_public_key(&node_id) {
+                            trusted.insert(dh_key, node_id);
+                        }
+                    }
+                }
+                // a node whose keys aren't generated yet simply has nothing to trust.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => eprintln!("Failed to read trusted keys in {}: {}", dir.display(), err),
+            }
+        }
+        *self.trusted.lock().unwrap() = trusted;
+    }
+}
+
+// An open connection, encrypted when it was made by a Noise transport.
+pub struct Connection {
+    stream: TcpStream,
+    local_addr: SocketAddr, // socket address of this end.
+    peer_addr: SocketAddr, // socket address of the other end.
+    noise: Option<TransportState>, // cipher state once the handshake is done.
+    remote_id: Option<Vec<u8>>, // node ID authenticated by the handshake.
+    dialed: Option<DialedSockets>, // where the closing time is recorded, if this node opened the connection.
+}
+
+impl Connection {
+    // Socket address of this end of the connection.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    // Socket address of the other end of the connection.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    // Node ID of the other end, only known on encrypted connections.
+    pub fn remote_id(&self) -> Option<&[u8]> {
+        self.remote_id.as_deref()
+    }
+
+    // Encodes a message and sends it as one frame.
+    pub async fn send(&mut self, message: &Message) -> io::Result<()> {
+        let Some(noise) = &mut self.noise else {
+            return send_message(&mut self.stream, message).await;
+        };
+        let mut frame = Vec::new();
+        send_message(&mut frame, message).await?;
+
+        let mut output = Vec::new();
+        let mut buffer = vec![0u8; MAX_NOISE_LEN];
+        for chunk in frame.chunks(MAX_NOISE_LEN - TAG_LEN) {
+            let len = noise.write_message(chunk, &mut buffer).map_err(noise_error)?;
+            output.extend_from_slice(&(len as u16).to_be_bytes());
+            output.extend_from_slice(&buffer[..len]);
+        }
+        self.stream.write_all(&output).await?;
+        self.stream.flush().await
+    }
+
+    // Reads one frame and decodes the message it carries.
+    pub async fn recv(&mut self) -> io::Result<Message> {
+        let Some(noise) = &mut self.noise else {
+            return recv_message(&mut self.stream).await;
+        };
+        let mut frame = Vec::new();
+        let mut buffer = vec![0u8; MAX_NOISE_LEN];
+        // the frame's own length prefix tells how many Noise messages carry it.
+        loop {
+            let message = read_noise_message(&mut self.stream).await?;
+            let len = noise.read_message(&message, &mut buffer).map_err(noise_error)?;
+            frame.extend_from_slice(&buffer[..len]);
+            if frame.len() < 4 {
+                continue;
+            }
+            let frame_len = u32::from_be_bytes(frame[..4].try_into().unwrap()) as usize;
+            if frame_len > MAX_FRAME_LEN || frame.len() > frame_len + 4 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid encrypted frame length"));
+            }
+            if frame.len() == frame_len + 4 {
+                return recv_message(&mut frame.as_slice()).await;
+            }
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Some(dialed) = &self.dialed {
+            if let Some(closed_at) = dialed.lock().unwrap().get_mut(&(self.local_addr, self.peer_addr)) {
+                *closed_at = Some(Instant::now());
+            }
+        }
+    }
+}
+
+// Writes one length-prefixed Noise message.
+async fn write_noise_message(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    stream.write_all(&(message.len() as u16).to_be_bytes()).await?;
+    stream.write_all(message).await?;
+    stream.flush().await
+}
+
+// Reads one length-prefixed Noise message.
+async fn read_noise_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let len = stream.read_u16().await? as usize;
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+fn noise_error(err: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tokio::net::TcpListener;
+
+    fn node_key(dir: &Path, name: &str) -> NodeKey {
+        NodeKey::load_or_generate(&dir.join(format!("{}.key", name))).unwrap()
+    }
+
+    // Opens a connection from `client` to `server` over loopback, returning both ends.
+    async fn connect_pair(client: &Transport, server: &Transport) -> (io::Result<Connection>, io::Result<Connection>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::join!(client.connect(&addr), async {
+            let (stream, _) = listener.accept().await.unwrap();
+            server.accept(stream).await
+        })
+    }
+
+    #[tokio::test]
+    async fn trusted_keys_complete_the_handshake() {
+        let dir = tempfile::tempdir().unwrap();
+        let (client_key, server_key) = (node_key(dir.path(), "client"), node_key(dir.path(), "server"));
+        let client = Transport::noise(&client_key, vec![dir.path().to_path_buf()]);
+        let server = Transport::noise(&server_key, vec![dir.path().to_path_buf()]);
+
+        let (sent, accepted) = connect_pair(&client, &server).await;
+        let (mut sent, mut accepted) = (sent.unwrap(), accepted.unwrap());
+        assert_eq!(sent.remote_id(), Some(server_key.node_id().as_slice()));
+        assert_eq!(accepted.remote_id(), Some(client_key.node_id().as_slice()));
+        let message = Message::Accept { addr: "127.0.0.1:9090".to_string() };
+        sent.send(&message).await.unwrap();
+        assert_eq!(accepted.recv().await.unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn untrusted_keys_are_rejected() {
+        let (trusted_dir, untrusted_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let server = Transport::noise(&node_key(trusted_dir.path(), "server"), vec![trusted_dir.path().to_path_buf()]);
+        // the client trusts the server, but the server doesn't know the client's key.
+        let client = Transport::noise(&node_key(untrusted_dir.path(), "client"),
+            vec![trusted_dir.path().to_path_buf(), untrusted_dir.path().to_path_buf()]);
+
+        let (sent, accepted) = connect_pair(&client, &server).await;
+        assert_eq!(accepted.err().map(|err| err.kind()), Some(io::ErrorKind::PermissionDenied));
+        // the client only learns the handshake failed once its first message can't get through.
+        if let Ok(mut sent) = sent {
+            let message = Message::Response { text: "ignored".to_string() };
+            let delivered = sent.send(&message).await.is_ok() && sent.recv().await.is_ok();
+            assert!(!delivered);
+        }
+    }
+
+    #[tokio::test]
+    async fn large_frames_span_several_noise_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = Transport::noise(&node_key(dir.path(), "client"), vec![dir.path().to_path_buf()]);
+        let server = Transport::noise(&node_key(dir.path(), "server"), vec![dir.path().to_path_buf()]);
+        let (sent, accepted) = connect_pair(&client, &server).await;
+        let (mut sent, mut accepted) = (sent.unwrap(), accepted.unwrap());
+
+        // three Noise messages' worth of payload, above the 65,519 bytes a single one carries.
+        let message = Message::Response { text: "x".repeat(3 * (MAX_NOISE_LEN - TAG_LEN)) };
+        let (sent_result, received) = tokio::join!(sent.send(&message), accepted.recv());
+        sent_result.unwrap();
+        assert_eq!(received.unwrap(), message);
+    }
+
+    #[tokio::test]
+    async fn dialed_connections_are_confirmed_until_the_grace_period_ends() {
+        let (client, server) = (Transport::plain(), Transport::plain());
+        let (sent, accepted) = connect_pair(&client, &server).await;
+        let (sent, accepted) = (sent.unwrap(), accepted.unwrap());
+        let (local, remote) = (sent.local_addr(), sent.peer_addr());
+        assert_eq!((accepted.peer_addr(), accepted.local_addr()), (local, remote));
+        assert!(client.dialed(local, remote));
+        assert!(!server.dialed(local, remote));
+        assert!(!client.dialed(remote, local));
+
+        // a message read after the sender hung up can still be checked.
+        drop(sent);
+        assert!(client.dialed(local, remote));
+        let closed_at = Instant::now() - DIALED_GRACE;
+        client.dialed.lock().unwrap().insert((local, remote), Some(closed_at));
+        assert!(!client.dialed(local, remote));
+    }
+}